@@ -0,0 +1,45 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NoSpace,
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    InvalidName,
+    Io,
+    Corrupt,
+    DirectoryFull,
+    InvalidSize,
+    NotEmpty,
+    InvalidArgument,
+    Busy,
+    CrossDevice,
+    BadDescriptor,
+    TooManyOpenFiles,
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            FsError::NoSpace => "No space left on device",
+            FsError::NotFound => "No such file or directory",
+            FsError::AlreadyExists => "File exists",
+            FsError::NotADirectory => "Not a directory",
+            FsError::IsADirectory => "Is a directory",
+            FsError::InvalidName => "Invalid file name",
+            FsError::Io => "Input/output error",
+            FsError::Corrupt => "File system is corrupted",
+            FsError::DirectoryFull => "Directory is full",
+            FsError::InvalidSize => "Volume size not supported",
+            FsError::NotEmpty => "Directory not empty",
+            FsError::InvalidArgument => "Invalid argument",
+            FsError::Busy => "Device or resource busy",
+            FsError::CrossDevice => "Invalid cross-device link",
+            FsError::BadDescriptor => "Bad file descriptor",
+            FsError::TooManyOpenFiles => "Too many open files",
+        };
+        write!(f, "{}", msg)
+    }
+}
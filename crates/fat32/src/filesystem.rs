@@ -0,0 +1,2248 @@
+use crate::error::FsError;
+use crate::hooks::{DateTime, Hooks};
+use crate::lfn::{self, LongNameBuilder};
+
+use core::fmt::Write;
+
+use alloc::string::ToString;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::vec;
+
+use core::{fmt};
+
+pub struct DirEntry {
+    pub name: [u8; 11],
+    pub attr: u8,
+    pub reserved: u8,
+    pub creation_time_tenths: u8,
+    pub creation_time: u16,
+    pub creation_date: u16,
+    pub last_access_date: u16,
+    pub first_cluster_high: u16,
+    pub write_time: u16,
+    pub write_date: u16,
+    pub first_cluster_low: u16,
+    pub file_size: u32,
+    // Name from the VFAT entries preceding this one, not part of the 32 on-disk bytes
+    pub long_name: Option<String>,
+}
+
+const SECTOR_SIZE: usize = 512;
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIG: u32 = 0xAA55_0000;
+
+// Value of the FSInfo fields when the count or the hint is not known
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// The spec caps directories at 65536 32-byte entries
+const MAX_DIR_ENTRIES: usize = 65536;
+
+// A directory entry together with where it lives on disk
+struct DirRecord {
+    entry: DirEntry,
+    // Byte position of the short entry on the device
+    pos: u64,
+    // Positions of the VFAT entries that hold its long name
+    lfn_slots: Vec<u64>,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct BiosParameterBlock {
+    pub _jmp: [u8; 3],
+    pub _oem: [u8; 8],
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_table_count: u8,
+    pub root_entries: u16,
+    pub total_sectors_16: u16,
+    pub media_descriptor: u8,
+    pub fat_size_16: u16,
+    pub sectors_per_track: u16,
+    pub heads_on_media: u16,
+    pub hidden_sectors: u32,
+    pub total_sectors_32: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ExtendedBootRecord32 {
+    pub fat_size_32: u32,
+    pub ext_flags: u16,
+    pub fat_version: u16,
+    pub root_cluster: u32,
+    pub fs_info: u16,
+    pub backup_boot: u16,
+    pub _reserved: [u8; 12],
+    pub drive_number: u8,
+    pub _win_nt_flags: u8,
+    pub signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fs_id: [u8; 8],
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FsInfo {
+    pub free_count: u32,
+    pub next_free: u32,
+}
+
+// Width of the FAT entries, which the spec derives from the number of clusters
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+pub fn str_to_fat_name(filename: &str) -> [u8; 11] {
+    let mut fat_name = [b' '; 11];
+
+    let parts: Vec<&str> = filename.split('.').collect();
+
+    if let Some(name) = parts.first() {
+        for (i, &b) in name.as_bytes().iter().take(8).enumerate() {
+            fat_name[i] = b.to_ascii_uppercase();
+        }
+    }
+
+    if parts.len() > 1
+        && let Some(ext) = parts.get(1) {
+            for (i, &b) in ext.as_bytes().iter().take(3).enumerate() {
+                fat_name[8 + i] = b.to_ascii_uppercase();
+            }
+        }
+
+    fat_name
+}
+
+pub fn is_dot_entry(entry: &DirEntry) -> bool {
+    &entry.name == b".          " || &entry.name == b"..         "
+}
+
+// Inconsistency found by `FileSystem::check`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    // FAT copy `fat_idx` differs from the first one in `entries` entries
+    FatCopyMismatch { fat_idx: u8, entries: usize },
+    // The chain runs into a free, reserved or out of range cluster after `cluster`
+    BrokenChain { path: String, cluster: u32 },
+    // `cluster` already belongs to another file or to an earlier part of the same chain
+    CrossLinked { path: String, cluster: u32 },
+    // The "." or ".." entry of a directory is missing or points to the wrong cluster
+    BadDotEntry { path: String },
+    // The file is larger than its cluster chain can hold
+    SizeMismatch { path: String, size: u32, chain_bytes: u64 },
+    // Clusters marked used in the FAT that no file or directory reaches
+    LostClusters { count: usize },
+    // The FSInfo free count disagrees with the FAT
+    WrongFreeCount { stored: u32, actual: u32 },
+}
+
+pub struct RamDisk {
+    data: Vec<u8>,
+}
+
+pub struct FileSystem<'a, D: BlockDevice> {
+    device: &'a mut D,
+    pub bpb: BiosParameterBlock,
+    pub ebr: ExtendedBootRecord32,
+    pub fat_start: u32,
+    pub fs_info: Option<FsInfo>,
+    hooks: Hooks,
+    fat_type: FatType,
+    // FAT12 and FAT16 keep the root directory in a fixed region between the FATs and the data
+    root_dir_start: u32,
+    root_dir_sectors: u32,
+    cluster_heap_start: u32,
+    // 0 on FAT12 and FAT16, whose root has no cluster
+    root_dir_cluster: u32,
+}
+
+pub trait BlockDevice {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8;512]) -> Result<(), FsError>;
+    fn write_sector(&mut self, lba: u64, buf: &[u8;512]) -> Result<(), FsError>;
+
+    // Makes sure every write so far has reached the underlying storage
+    fn sync(&mut self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+// Lets a device be used through a borrow, for example by a partition that doesn't own it
+impl<D: BlockDevice + ?Sized> BlockDevice for &mut D {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8;512]) -> Result<(), FsError> {
+        (**self).read_sector(lba, buf)
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8;512]) -> Result<(), FsError> {
+        (**self).write_sector(lba, buf)
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        (**self).sync()
+    }
+}
+
+// FAT dates count years from 1980, up to 2107
+pub fn fat_date(time: &DateTime) -> u16 {
+    let year = time.year.clamp(1980, 2107) - 1980;
+    (year << 9) | ((time.month as u16) << 5) | time.day as u16
+}
+
+// FAT times only have a two second resolution
+pub fn fat_time(time: &DateTime) -> u16 {
+    ((time.hour as u16) << 11) | ((time.minute as u16) << 5) | (time.second as u16 / 2)
+}
+
+impl DirEntry{
+    pub fn new(name: [u8; 11], cluster: u32, attr: u8, now: &DateTime) -> Self {
+        DirEntry {
+            name,
+            attr,
+            reserved: 0,
+            // The odd second that fat_time can't hold, in hundredths
+            creation_time_tenths: (now.second % 2) * 100,
+            creation_time: fat_time(now),
+            creation_date: fat_date(now),
+            last_access_date: fat_date(now),
+            first_cluster_high: (cluster >> 16) as u16,
+            write_time: fat_time(now),
+            write_date: fat_date(now),
+            first_cluster_low: (cluster & 0xFFFF) as u16,
+            file_size: 0,
+            long_name: None,
+        }
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        self.first_cluster_high = (cluster >> 16) as u16;
+        self.first_cluster_low = (cluster & 0xFFFF) as u16;
+    }
+
+    // A write also counts as an access
+    pub fn set_modified(&mut self, time: &DateTime) {
+        self.write_time = fat_time(time);
+        self.write_date = fat_date(time);
+        self.last_access_date = fat_date(time);
+    }
+
+    pub fn first_cluster(&self) -> u32 {
+        ((self.first_cluster_high as u32) << 16) | (self.first_cluster_low as u32)
+    }
+
+    pub fn get_name(&self) -> Result<String, core::str::Utf8Error> {
+        if let Some(long_name) = &self.long_name {
+            return Ok(long_name.clone());
+        }
+        self.short_name()
+    }
+
+    pub fn short_name(&self) -> Result<String, core::str::Utf8Error> {
+        let (name_bytes, ext_bytes) = self.name.split_at(8);
+
+        let name_str = core::str::from_utf8(name_bytes)?.trim_end();
+        let ext_str = core::str::from_utf8(ext_bytes)?.trim_end();
+
+        if ext_str.is_empty() {
+            Ok(name_str.to_string())
+        } else {
+            Ok(format!("{}.{}", name_str, ext_str))
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attr & 0x10 != 0
+    }
+
+    // FAT names are case insensitive, either the long or the short name can be used
+    pub fn matches_name(&self, name: &str) -> bool {
+        let long_match = self.long_name.as_ref()
+            .is_some_and(|long_name| long_name.to_lowercase() == name.to_lowercase());
+
+        long_match || lfn::short_name_to_string(&self.name).eq_ignore_ascii_case(name)
+    }
+
+
+    pub fn serialize(&self) -> [u8; 32] {
+        let mut buffer = [0u8; 32];
+
+        buffer[0..11].copy_from_slice(&self.name);
+
+        buffer[11] = self.attr;
+        buffer[12] = self.reserved;
+        buffer[13] = self.creation_time_tenths;
+
+        buffer[14..16].copy_from_slice(&self.creation_time.to_le_bytes());
+        buffer[16..18].copy_from_slice(&self.creation_date.to_le_bytes());
+        buffer[18..20].copy_from_slice(&self.last_access_date.to_le_bytes());
+        buffer[20..22].copy_from_slice(&self.first_cluster_high.to_le_bytes());
+        buffer[22..24].copy_from_slice(&self.write_time.to_le_bytes());
+        buffer[24..26].copy_from_slice(&self.write_date.to_le_bytes());
+        buffer[26..28].copy_from_slice(&self.first_cluster_low.to_le_bytes());
+        buffer[28..32].copy_from_slice(&self.file_size.to_le_bytes());
+
+        buffer
+    }
+
+    pub fn deserialize(data: &[u8; 32]) -> Self {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&data[0..11]);
+
+        Self {
+            name,
+            attr: data[11],
+            reserved: data[12],
+            creation_time_tenths: data[13],
+            creation_time: u16::from_le_bytes(data[14..16].try_into().unwrap()),
+            creation_date: u16::from_le_bytes(data[16..18].try_into().unwrap()),
+            last_access_date: u16::from_le_bytes(data[18..20].try_into().unwrap()),
+            first_cluster_high: u16::from_le_bytes(data[20..22].try_into().unwrap()),
+            write_time: u16::from_le_bytes(data[22..24].try_into().unwrap()),
+            write_date: u16::from_le_bytes(data[24..26].try_into().unwrap()),
+            first_cluster_low: u16::from_le_bytes(data[26..28].try_into().unwrap()),
+            file_size: u32::from_le_bytes(data[28..32].try_into().unwrap()),
+            long_name: None,
+        }
+    }
+}
+
+impl<'a, D: BlockDevice> FileSystem<'a, D> {
+    pub fn init_fats(&mut self) -> Result<(), FsError> {
+        let fat_size_sectors = self.fat_size_sectors() as u64;
+        let media_descriptor = self.bpb.media_descriptor;
+
+        let mut first_sector = [0u8; SECTOR_SIZE];
+
+        match self.fat_type {
+            FatType::Fat32 => {
+                // Entry 0: 0x0FFF_FF0 | media_descriptor
+                let entry0 = 0x0FFFFFF0u32 | (media_descriptor as u32);
+                first_sector[0..4].copy_from_slice(&entry0.to_le_bytes());
+
+                // Entry 1: reserved cluster (0xFFFF_FFFF)
+                first_sector[4..8].copy_from_slice(&0xFFFFFFFFu32.to_le_bytes());
+            }
+            // Same entries, 0xFF8 and 0xFFF packed in three bytes
+            FatType::Fat12 => first_sector[0..3].copy_from_slice(&[media_descriptor, 0xFF, 0xFF]),
+            FatType::Fat16 => first_sector[0..4].copy_from_slice(&[media_descriptor, 0xFF, 0xFF, 0xFF]),
+        }
+
+        let empty_sector = [0u8; SECTOR_SIZE];
+
+        for i in 0..self.bpb.fat_table_count as u64 {
+            let fat_lba = self.fat_start as u64 + i * fat_size_sectors;
+
+            self.device.write_sector(fat_lba, &first_sector)?;
+            for sector in 1..fat_size_sectors {
+                self.device.write_sector(fat_lba + sector, &empty_sector)?;
+            }
+        }
+
+        // Every cluster is free on an empty FAT
+        let cluster_count = self.cluster_count();
+        if let Some(fs_info) = self.fs_info.as_mut() {
+            fs_info.free_count = cluster_count;
+            fs_info.next_free = 2;
+        }
+        self.write_fs_info()
+    }
+
+    pub fn count_occupied_clusters(&mut self) -> Result<usize, FsError> {
+        let fat = self.read_fat_copy(0)?;
+        Ok(fat.iter().filter(|&&entry| entry != 0).count())
+    }
+
+    // Sector and byte offset inside it that hold the entry of `cluster_idx` in FAT copy `fat_idx`
+    fn fat_entry_location(&self, cluster_idx: u32, fat_idx: u8) -> (u64, usize) {
+        let byte_offset = self.fat_type.entry_offset(cluster_idx);
+        let fat_lba = self.fat_start as u64 + fat_idx as u64 * self.fat_size_sectors() as u64;
+
+        (fat_lba + (byte_offset / SECTOR_SIZE) as u64, byte_offset % SECTOR_SIZE)
+    }
+
+    // The sector holding an entry and the next one, which only a FAT12 entry can spill into
+    fn read_entry_sectors(&mut self, lba: u64, offset: usize, buf: &mut [u8; 2 * SECTOR_SIZE]) -> Result<bool, FsError> {
+        let (first, second) = buf.split_at_mut(SECTOR_SIZE);
+        self.device.read_sector(lba, first.try_into().unwrap())?;
+
+        let spills = offset + self.fat_type.entry_bytes() > SECTOR_SIZE;
+        if spills {
+            self.device.read_sector(lba + 1, second.try_into().unwrap())?;
+        }
+        Ok(spills)
+    }
+
+    // End of chain and bad cluster marks come back as their FAT32 values whatever the FAT type
+    pub fn read_fat_entry(&mut self, cluster_idx: u32) -> Result<u32, FsError> {
+        let (lba, offset) = self.fat_entry_location(cluster_idx, 0);
+
+        let mut sectors = [0u8; 2 * SECTOR_SIZE];
+        self.read_entry_sectors(lba, offset, &mut sectors)?;
+
+        Ok(self.fat_type.decode(cluster_idx, &sectors[offset..]))
+    }
+
+    fn write_fat_entry(&mut self, cluster_idx: u32, value: u32) -> Result<(), FsError> {
+        let mut sectors = [0u8; 2 * SECTOR_SIZE];
+
+        for i in 0..self.bpb.fat_table_count {
+            let (lba, offset) = self.fat_entry_location(cluster_idx, i);
+            let spills = self.read_entry_sectors(lba, offset, &mut sectors)?;
+
+            self.fat_type.encode(cluster_idx, value, &mut sectors[offset..]);
+
+            let (first, second) = sectors.split_at(SECTOR_SIZE);
+            self.device.write_sector(lba, first.try_into().unwrap())?;
+            if spills {
+                self.device.write_sector(lba + 1, second.try_into().unwrap())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn allocate_cluster(&mut self) -> Result<u32, FsError> {
+        if (self.hooks.debug_enabled)() {
+            let log = "Trying to find a free cluster".to_string();
+            (self.hooks.debug)(&log);
+        }
+
+        let num_fat_entries = self.fat_entry_count() as u32;
+
+        // Start at the FSInfo hint and wrap around to the beginning of the FAT
+        let hint = self.fs_info
+            .map(|fs_info| fs_info.next_free)
+            .filter(|&next_free| next_free >= 2 && next_free < num_fat_entries)
+            .unwrap_or(2);
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        let mut loaded_lba = None;
+
+        let mut free_cluster = None;
+
+        for cluster_idx in (hint..num_fat_entries).chain(2..hint) {
+            let (lba, offset) = self.fat_entry_location(cluster_idx, 0);
+
+            let entry = if offset + self.fat_type.entry_bytes() > SECTOR_SIZE {
+                self.read_fat_entry(cluster_idx)?
+            } else {
+                if loaded_lba != Some(lba) {
+                    self.device.read_sector(lba, &mut sector)?;
+                    loaded_lba = Some(lba);
+                }
+                self.fat_type.decode(cluster_idx, &sector[offset..])
+            };
+
+            if entry == 0 {
+                free_cluster = Some(cluster_idx);
+                break;
+            }
+        }
+
+        let cluster_idx = free_cluster.ok_or(FsError::NoSpace)?;
+
+        // Mark the cluster as end of chain in every FAT copy
+        self.write_fat_entry(cluster_idx, 0x0FFF_FFF8)?;
+
+        if let Some(fs_info) = self.fs_info.as_mut() {
+            if fs_info.free_count != FSINFO_UNKNOWN {
+                fs_info.free_count = fs_info.free_count.saturating_sub(1);
+            }
+            fs_info.next_free = cluster_idx + 1;
+        }
+        self.write_fs_info()?;
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Free cluster found at {}", cluster_idx);
+            (self.hooks.debug)(&log);
+        }
+
+        Ok(cluster_idx)
+    }
+
+    pub fn free_cluster_chain(&mut self, start_cluster: u32) -> Result<(), FsError> {
+        let chain = self.cluster_chain(start_cluster)?;
+        for &cluster in chain.iter() {
+            self.write_fat_entry(cluster, 0)?;
+        }
+
+        if let Some(fs_info) = self.fs_info.as_mut()
+            && fs_info.free_count != FSINFO_UNKNOWN {
+                fs_info.free_count += chain.len() as u32;
+            }
+        self.write_fs_info()
+    }
+
+    // Number of free clusters, from FSInfo when it is known so the FAT doesn't have to be scanned
+    pub fn free_clusters(&mut self) -> Result<u32, FsError> {
+        if let Some(fs_info) = self.fs_info
+            && fs_info.free_count != FSINFO_UNKNOWN {
+                return Ok(fs_info.free_count);
+            }
+
+        let mut free = 0;
+        for cluster_idx in 2..self.cluster_count() + 2 {
+            if self.read_fat_entry(cluster_idx)? == 0 {
+                free += 1;
+            }
+        }
+
+        if let Some(fs_info) = self.fs_info.as_mut() {
+            fs_info.free_count = free;
+        }
+        self.write_fs_info()?;
+
+        Ok(free)
+    }
+
+    fn write_fs_info(&mut self) -> Result<(), FsError> {
+        if let Some(fs_info) = self.fs_info {
+            self.device.write_sector(self.ebr.fs_info as u64, &fs_info.serialize())?;
+        }
+        Ok(())
+    }
+
+    pub fn zero_cluster_data(&mut self, cluster_idx: usize) -> Result<(), FsError> {
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Trying to empty the cluster: {}",cluster_idx);
+            (self.hooks.debug)(&log);
+        }
+
+        let first_sector = self.cluster_sector(cluster_idx as u32)?;
+        let empty_sector = [0u8; SECTOR_SIZE];
+
+        for i in 0..self.bpb.sectors_per_cluster as u64 {
+            self.device.write_sector(first_sector + i, &empty_sector)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_cluster(&mut self, cluster_idx: u32, buf: &mut [u8]) -> Result<(), FsError> {
+        let first_sector = self.cluster_sector(cluster_idx)?;
+        let mut sector = [0u8; SECTOR_SIZE];
+
+        for (i, chunk) in buf.chunks_mut(SECTOR_SIZE).take(self.bpb.sectors_per_cluster as usize).enumerate() {
+            self.device.read_sector(first_sector + i as u64, &mut sector)?;
+            chunk.copy_from_slice(&sector[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    pub fn write_cluster(&mut self, cluster_idx: u32, buf: &[u8]) -> Result<(), FsError> {
+        let first_sector = self.cluster_sector(cluster_idx)?;
+        let mut sector = [0u8; SECTOR_SIZE];
+
+        for (i, chunk) in buf.chunks(SECTOR_SIZE).take(self.bpb.sectors_per_cluster as usize).enumerate() {
+            sector[..chunk.len()].copy_from_slice(chunk);
+            sector[chunk.len()..].fill(0);
+            self.device.write_sector(first_sector + i as u64, &sector)?;
+        }
+        Ok(())
+    }
+
+    pub fn return_tree(&mut self, cluster_idx: u32, depth: usize) -> Result<String, FsError> {
+        let mut ancestors = vec![cluster_idx];
+        self.subtree(cluster_idx, depth, &mut ancestors)
+    }
+
+    // `ancestors` holds the directories above this one, so a corrupted tree that loops back
+    // into one of them is not followed forever
+    fn subtree(&mut self, cluster_idx: u32, depth: usize, ancestors: &mut Vec<u32>) -> Result<String, FsError> {
+        let entries = self.read_dir_entries(cluster_idx as usize)?;
+
+        let mut out = String::new();
+
+        for entry in entries {
+            for _ in 0..depth {
+                out.push_str("\\ ");
+            }
+
+            if entry.is_directory() {
+                let name = entry.get_name().unwrap();
+                let cluster = entry.first_cluster();
+
+                if name == "." || name == ".." {
+                    let _ = writeln!(out, "* {}", name.trim_end());
+                } else if ancestors.contains(&cluster) {
+                    let _ = writeln!(out, "* {} (loops back to a parent directory)", name.trim_end());
+                } else {
+                    let _ = writeln!(out, "* {}", name.trim_end());
+
+                    ancestors.push(cluster);
+                    let subtree = self.subtree(cluster, depth + 1, ancestors);
+                    ancestors.pop();
+                    out.push_str(&subtree?);
+                }
+
+            } else {
+                let _ = writeln!(out, "* {}", entry.get_name().unwrap());
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn print_tree(&mut self, cluster_idx: u32, depth: usize) -> Result<(), FsError> {
+        let tree = self.return_tree(cluster_idx, depth)?;
+        (self.hooks.log)(&tree);
+        Ok(())
+    }
+
+    pub fn allocate_dir_entry(&mut self, new_entry: DirEntry, cluster_idx: usize) -> Result<u32, FsError>{
+        let (entry_idx, slots) = self.allocate_dir_slots(cluster_idx as u32, 1)?;
+
+        self.write_raw_entry(slots[0], &new_entry.serialize())?;
+
+        Ok(entry_idx as u32)
+    }
+
+    // Finds `count` consecutive free slots in the directory, chaining more clusters if needed.
+    // Returns the index of the first slot and the position of each one.
+    fn allocate_dir_slots(&mut self, dir_cluster: u32, count: usize) -> Result<(usize, Vec<u64>), FsError> {
+        loop {
+            let slots = self.read_dir_slots(dir_cluster)?;
+
+            let mut run_start = 0;
+            let mut run_len = 0;
+
+            for (entry_idx, (_, raw)) in slots.iter().enumerate() {
+                let first_byte = raw[0];
+                if first_byte == 0x00 || first_byte == 0xE5 {
+                    if run_len == 0 {
+                        run_start = entry_idx;
+                    }
+                    run_len += 1;
+                    if run_len == count {
+                        let positions = slots[run_start..=entry_idx].iter().map(|(pos, _)| *pos).collect();
+                        return Ok((run_start, positions));
+                    }
+                } else {
+                    run_len = 0;
+                }
+            }
+
+            // The fixed root can't grow, nor can a directory hold more than 65536 entries
+            if self.is_fixed_root(dir_cluster) || slots.len() >= MAX_DIR_ENTRIES {
+                return Err(FsError::DirectoryFull);
+            }
+
+            // Not enough room, chain another cluster to the directory
+            let last_cluster = *self.cluster_chain(dir_cluster)?.last().ok_or(FsError::Corrupt)?;
+            let new_cluster = self.next_cluster_or_allocate(last_cluster)?;
+
+            if (self.hooks.debug_enabled)() {
+                let log = format!("Directory in cluster {} extended with cluster {}", dir_cluster, new_cluster);
+                (self.hooks.debug)(&log);
+            }
+        }
+    }
+
+    // Stores `entry` under `filename` in the directory, adding VFAT entries and a
+    // unique short name alias when the name doesn't fit in 8.3
+    fn add_entry(&mut self, dir_cluster: u32, filename: &str, mut entry: DirEntry) -> Result<(), FsError> {
+        let records = self.scan_dir(dir_cluster)?;
+        if records.iter().any(|record| record.entry.matches_name(filename)) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let (basis, lossy) = lfn::short_name_basis(filename).ok_or(FsError::InvalidName)?;
+        let taken = |name: &[u8; 11]| records.iter().any(|record| &record.entry.name == name);
+
+        entry.name = if !lossy && !taken(&basis) {
+            basis
+        } else {
+            (1..1_000_000)
+                .map(|n| lfn::with_numeric_tail(&basis, n))
+                .find(|name| !taken(name))
+                .ok_or(FsError::DirectoryFull)?
+        };
+
+        let mut raw_entries = Vec::new();
+        if lfn::needs_long_name(filename, &entry.name) {
+            raw_entries.extend(lfn::long_name_entries(filename, &entry.name).ok_or(FsError::InvalidName)?);
+        }
+        raw_entries.push(entry.serialize());
+
+        let (_, slots) = self.allocate_dir_slots(dir_cluster, raw_entries.len())?;
+
+        for (pos, raw) in slots.into_iter().zip(raw_entries.iter()) {
+            self.write_raw_entry(pos, raw)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_dir(&mut self, parent_dir_cluster: u32, filename: &str) -> Result<(), FsError> {
+        let cluster = self.allocate_cluster()?;
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Trying to create directory: {} in cluster {} with parent cluster {}", filename, cluster, parent_dir_cluster);
+            (self.hooks.debug)(&log);
+        }
+
+        // Whatever fails once the cluster is taken, it is given back
+        if let Err(err) = self.fill_new_dir(parent_dir_cluster, filename, cluster) {
+            self.free_cluster_chain(cluster)?;
+            return Err(err);
+        }
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Directory {}/ successfuly created in cluster {} with parent cluster {}", filename, cluster, parent_dir_cluster);
+            (self.hooks.debug)(&log);
+        }
+        Ok(())
+    }
+
+    // Empties `cluster`, writes its "." and ".." entries and links it into the parent as `filename`
+    fn fill_new_dir(&mut self, parent_dir_cluster: u32, filename: &str, cluster: u32) -> Result<(), FsError> {
+        let entry: DirEntry = DirEntry::new(str_to_fat_name(filename), cluster, 0x10, &self.now());
+
+        self.zero_cluster_data(cluster as usize)?;
+
+        let dot = DirEntry::new(*b".          ", cluster, 0x10, &self.now());
+        let dot2 = DirEntry::new(*b"..         ", self.dotdot_cluster(parent_dir_cluster), 0x10, &self.now());
+
+        self.allocate_dir_entry(dot, cluster as usize)?;
+        self.allocate_dir_entry(dot2, cluster as usize)?;
+
+        self.add_entry(parent_dir_cluster, filename, entry)
+    }
+
+    pub fn create_root_dir(&mut self) -> Result<(), FsError> {
+        // A fixed root only needs to be emptied, it has no "." or ".." entries
+        if self.root_dir_sectors > 0 {
+            let empty_sector = [0u8; SECTOR_SIZE];
+            for i in 0..self.root_dir_sectors as u64 {
+                self.device.write_sector(self.root_dir_start as u64 + i, &empty_sector)?;
+            }
+            return Ok(());
+        }
+
+        let root_cluster = self.root_dir_cluster as usize;
+
+        self.zero_cluster_data(root_cluster)?;
+
+        let dot = DirEntry::new(*b".          ", root_cluster as u32, 0x10, &self.now());
+        let dot2 = DirEntry::new(*b"..         ", root_cluster as u32, 0x10, &self.now());
+
+        // The root must be marked as used before its entries are written,
+        // otherwise its chain looks empty
+        self.write_fat_entry(root_cluster as u32, 0x0FFF_FFFFu32)?;
+
+        if let Some(fs_info) = self.fs_info.as_mut()
+            && fs_info.free_count != FSINFO_UNKNOWN {
+                fs_info.free_count = fs_info.free_count.saturating_sub(1);
+            }
+        self.write_fs_info()?;
+
+        self.allocate_dir_entry(dot, root_cluster)?;
+        self.allocate_dir_entry(dot2, root_cluster)?;
+
+        Ok(())
+    }
+
+    pub fn find_dir_in(&mut self, cluster: u32, name: &str) -> Result<u32, FsError> {
+        let (_, entry) = self.find_entry(cluster, name)?;
+        if !entry.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(entry.first_cluster())
+    }
+
+    pub fn read_dir_entries(&mut self, cluster_idx: usize) -> Result<Vec<DirEntry>, FsError> {
+        Ok(self.scan_dir(cluster_idx as u32)?
+            .into_iter()
+            .map(|record| record.entry)
+            .collect())
+    }
+
+    // Like `read_dir_entries`, with the byte position of each short entry on the device
+    pub fn read_dir_positions(&mut self, cluster_idx: u32) -> Result<Vec<(u64, DirEntry)>, FsError> {
+        Ok(self.scan_dir(cluster_idx)?
+            .into_iter()
+            .map(|record| (record.pos, record.entry))
+            .collect())
+    }
+
+    // Whether `dir_cluster` is the root region of a FAT12 or FAT16 volume
+    fn is_fixed_root(&self, dir_cluster: u32) -> bool {
+        self.root_dir_sectors > 0 && dir_cluster == self.root_dir_cluster
+    }
+
+    // Every 32-byte slot of a directory across its whole cluster chain, with its position
+    fn read_dir_slots(&mut self, dir_cluster: u32) -> Result<Vec<(u64, [u8; 32])>, FsError> {
+        let mut slots = Vec::new();
+
+        if self.is_fixed_root(dir_cluster) {
+            let mut sector = [0u8; SECTOR_SIZE];
+            let entry_count = self.bpb.root_entries as usize;
+
+            for lba in self.root_dir_start as u64..(self.root_dir_start + self.root_dir_sectors) as u64 {
+                self.device.read_sector(lba, &mut sector)?;
+
+                for (entry_idx, raw) in sector.chunks_exact(32).enumerate() {
+                    if slots.len() == entry_count {
+                        break;
+                    }
+                    slots.push((lba * SECTOR_SIZE as u64 + (entry_idx * 32) as u64, raw.try_into().unwrap()));
+                }
+            }
+            return Ok(slots);
+        }
+
+        let mut cluster_buf = vec![0u8; self.cluster_size()];
+        for cluster in self.cluster_chain(dir_cluster)? {
+            self.read_cluster(cluster, &mut cluster_buf)?;
+
+            let base = self.cluster_sector(cluster)? * SECTOR_SIZE as u64;
+            for (entry_idx, raw) in cluster_buf.chunks_exact(32).enumerate() {
+                slots.push((base + (entry_idx * 32) as u64, raw.try_into().unwrap()));
+            }
+        }
+        Ok(slots)
+    }
+
+    // Walks every slot of a directory, joining VFAT entries with the short entry they belong to.
+    // Deleted entries and the volume label are skipped.
+    fn scan_dir(&mut self, dir_cluster: u32) -> Result<Vec<DirRecord>, FsError> {
+        let slots = self.read_dir_slots(dir_cluster)?;
+
+        let mut records = Vec::new();
+        let mut long_name = LongNameBuilder::new();
+        let mut lfn_slots = Vec::new();
+
+        for (pos, raw) in slots {
+            let first_byte = raw[0];
+
+            if first_byte == 0x00 {
+                break; // no more entries
+            }
+            if first_byte == 0xE5 {
+                long_name.reset();
+                lfn_slots.clear();
+                continue; // deleted entry, skip
+            }
+            if lfn::is_lfn_entry(&raw) {
+                if raw[0] & lfn::LAST_LFN_ENTRY != 0 {
+                    lfn_slots.clear();
+                }
+                long_name.push(&raw);
+                lfn_slots.push(pos);
+                continue;
+            }
+
+            let mut entry = DirEntry::deserialize(&raw);
+            if entry.attr & 0x08 != 0 {
+                long_name.reset();
+                lfn_slots.clear();
+                continue; // volume label
+            }
+
+            entry.long_name = long_name.finish(&entry.name);
+
+            // Orphaned VFAT entries don't belong to this entry
+            let lfn_slots = if entry.long_name.is_some() {
+                core::mem::take(&mut lfn_slots)
+            } else {
+                lfn_slots.clear();
+                Vec::new()
+            };
+
+            records.push(DirRecord {
+                entry,
+                pos,
+                lfn_slots,
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn create_file(&mut self, parent_dir_cluster: u32, filename: &str) -> Result<(), FsError> {
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Trying to create file {} with parent cluster {}", filename, parent_dir_cluster);
+            (self.hooks.debug)(&log);
+        }
+
+        // An empty file has no clusters, the first one is allocated when something is written
+        let entry: DirEntry = DirEntry::new(str_to_fat_name(filename), 0, 0x20, &self.now());
+        self.add_entry(parent_dir_cluster, filename, entry)?;
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("File {} successfuly created with parent cluster {}", filename, parent_dir_cluster);
+            (self.hooks.debug)(&log);
+        }
+        Ok(())
+    }
+
+    fn fat_size_sectors(&self) -> usize {
+        match self.bpb.fat_size_16 {
+            0 => self.ebr.fat_size_32 as usize,
+            n => n as usize,
+        }
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.bpb.sectors_per_cluster as usize * self.bpb.bytes_per_sector as usize
+    }
+
+    // First sector of the given cluster
+    // First sector of a cluster. The data region starts with cluster 2, a lower one can only come
+    // from a damaged volume.
+    fn cluster_sector(&self, cluster_idx: u32) -> Result<u64, FsError> {
+        let data_idx = cluster_idx.checked_sub(2).ok_or(FsError::Corrupt)?;
+        Ok(self.cluster_heap_start as u64 + data_idx as u64 * self.bpb.sectors_per_cluster as u64)
+    }
+
+    pub fn sync(&mut self) -> Result<(), FsError> {
+        self.device.sync()
+    }
+
+    pub fn root_cluster(&self) -> u32 {
+        self.root_dir_cluster
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    fn now(&self) -> DateTime {
+        (self.hooks.now)()
+    }
+
+    // Number of clusters that fit in the data region
+    pub fn cluster_count(&self) -> u32 {
+        let total_sectors = match self.bpb.total_sectors_16 {
+            0 => self.bpb.total_sectors_32 as usize,
+            n => n as usize,
+        };
+
+        let data_sectors = total_sectors.saturating_sub(self.cluster_heap_start as usize);
+
+        (data_sectors / self.bpb.sectors_per_cluster as usize) as u32
+    }
+
+    // Returns the cluster that follows `cluster_idx`, growing the chain if it ends there
+    fn next_cluster_or_allocate(&mut self, cluster_idx: u32) -> Result<u32, FsError> {
+        let next = self.read_fat_entry(cluster_idx)?;
+        if (2..0x0FFF_FFF8).contains(&next) {
+            return Ok(next);
+        }
+
+        let new_cluster = self.allocate_cluster()?;
+        self.zero_cluster_data(new_cluster as usize)?;
+        self.write_fat_entry(cluster_idx, new_cluster)?;
+
+        Ok(new_cluster)
+    }
+
+    // The first cluster of a file, allocating one if it is empty and has none yet
+    fn first_cluster_or_allocate(&mut self, entry: &mut DirEntry) -> Result<u32, FsError> {
+        if entry.first_cluster() < 2 {
+            let cluster = self.allocate_cluster()?;
+            self.zero_cluster_data(cluster as usize)?;
+            entry.set_first_cluster(cluster);
+        }
+        Ok(entry.first_cluster())
+    }
+
+    // What ".." stores for the parent directory `dir_cluster`, which is 0 for the root
+    fn dotdot_cluster(&self, dir_cluster: u32) -> u32 {
+        if dir_cluster == self.root_dir_cluster { 0 } else { dir_cluster }
+    }
+
+    // Clusters of the chain starting at `start_cluster`, in order
+    pub fn cluster_chain(&mut self, start_cluster: u32) -> Result<Vec<u32>, FsError> {
+        let max_clusters = self.cluster_count() as usize;
+        let end = self.fat_entry_count() as u32;
+
+        let mut chain = Vec::new();
+        let mut cluster = start_cluster;
+
+        // A chain can't be longer than the volume, stop there if the FAT has a loop
+        while cluster >= 2 && cluster < end && chain.len() < max_clusters {
+            chain.push(cluster);
+            cluster = self.read_fat_entry(cluster)?;
+        }
+        Ok(chain)
+    }
+
+    // Looks up `filename` in the directory and returns the position of its entry
+    pub fn find_entry(&mut self, dir_cluster: u32, filename: &str) -> Result<(u64, DirEntry), FsError> {
+        self.find_record(dir_cluster, filename)
+            .map(|record| (record.pos, record.entry))
+    }
+
+    fn find_record(&mut self, dir_cluster: u32, filename: &str) -> Result<DirRecord, FsError> {
+        self.scan_dir(dir_cluster)?
+            .into_iter()
+            .find(|record| record.entry.matches_name(filename))
+            .ok_or(FsError::NotFound)
+    }
+
+    // Rewrites the 32 bytes at `pos`, a byte position on the device
+    fn write_raw_entry(&mut self, pos: u64, raw: &[u8; 32]) -> Result<(), FsError> {
+        let lba = pos / SECTOR_SIZE as u64;
+        let offset = (pos % SECTOR_SIZE as u64) as usize;
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.device.read_sector(lba, &mut sector)?;
+        sector[offset..offset + 32].copy_from_slice(raw);
+        self.device.write_sector(lba, &sector)
+    }
+
+    fn update_entry(&mut self, pos: u64, entry: &DirEntry) -> Result<(), FsError> {
+        self.write_raw_entry(pos, &entry.serialize())
+    }
+
+    // Writes `data` into the chain starting at `first_cluster`, beginning at byte `offset`.
+    // Returns the cluster holding the last written byte.
+    fn write_chain(&mut self, first_cluster: u32, offset: usize, data: &[u8]) -> Result<u32, FsError> {
+        let cluster_size = self.cluster_size();
+
+        let mut cluster = first_cluster;
+        for _ in 0..offset / cluster_size {
+            cluster = self.next_cluster_or_allocate(cluster)?;
+        }
+
+        let mut cluster_buf = vec![0u8; cluster_size];
+        let mut in_cluster = offset % cluster_size;
+        let mut written = 0;
+
+        loop {
+            let count = (cluster_size - in_cluster).min(data.len() - written);
+
+            // Only a partially overwritten cluster needs its old content
+            if count < cluster_size {
+                self.read_cluster(cluster, &mut cluster_buf)?;
+            }
+            cluster_buf[in_cluster..in_cluster + count].copy_from_slice(&data[written..written + count]);
+            self.write_cluster(cluster, &cluster_buf)?;
+
+            written += count;
+            in_cluster = 0;
+
+            if written == data.len() {
+                return Ok(cluster);
+            }
+            cluster = self.next_cluster_or_allocate(cluster)?;
+        }
+    }
+
+    pub fn read_file(&mut self, parent_dir_cluster: u32, filename: &str) -> Result<Vec<u8>, FsError> {
+        let (entry_pos, mut entry) = self.find_entry(parent_dir_cluster, filename)?;
+        if entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        let cluster_size = self.cluster_size();
+        let file_size = entry.file_size as usize;
+
+        let mut content = Vec::with_capacity(file_size);
+        let mut cluster_buf = vec![0u8; cluster_size];
+        let mut cluster = entry.first_cluster();
+
+        while content.len() < file_size {
+            if !(2..0x0FFF_FFF8).contains(&cluster) {
+                // Chain ended before file_size bytes were read
+                return Err(FsError::Corrupt);
+            }
+
+            let count = cluster_size.min(file_size - content.len());
+
+            self.read_cluster(cluster, &mut cluster_buf)?;
+            content.extend_from_slice(&cluster_buf[..count]);
+
+            cluster = self.read_fat_entry(cluster)?;
+        }
+
+        // The access time is only a date, the entry is rewritten once a day at most
+        let today = fat_date(&self.now());
+        if entry.last_access_date != today {
+            entry.last_access_date = today;
+            self.update_entry(entry_pos, &entry)?;
+        }
+
+        Ok(content)
+    }
+
+    pub fn write_file(&mut self, parent_dir_cluster: u32, filename: &str, data: &[u8]) -> Result<(), FsError> {
+        let (entry_pos, mut entry) = self.find_entry(parent_dir_cluster, filename)?;
+        if entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+        if data.len() > u32::MAX as usize {
+            return Err(FsError::NoSpace);
+        }
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Trying to write {} bytes to file {} in cluster {}", data.len(), filename, entry.first_cluster());
+            (self.hooks.debug)(&log);
+        }
+
+        if data.is_empty() {
+            self.free_cluster_chain(entry.first_cluster())?;
+            entry.set_first_cluster(0);
+        } else {
+            let first_cluster = self.first_cluster_or_allocate(&mut entry)?;
+            let last_cluster = self.write_chain(first_cluster, 0, data)?;
+
+            // Release whatever the previous content used past the new end
+            let next = self.read_fat_entry(last_cluster)?;
+            if (2..0x0FFF_FFF8).contains(&next) {
+                self.write_fat_entry(last_cluster, 0x0FFF_FFFF)?;
+                self.free_cluster_chain(next)?;
+            }
+        }
+
+        entry.file_size = data.len() as u32;
+        entry.set_modified(&self.now());
+        self.update_entry(entry_pos, &entry)
+    }
+
+    pub fn append(&mut self, parent_dir_cluster: u32, filename: &str, data: &[u8]) -> Result<(), FsError> {
+        let (entry_pos, mut entry) = self.find_entry(parent_dir_cluster, filename)?;
+        if entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Trying to append {} bytes to file {} in cluster {}", data.len(), filename, entry.first_cluster());
+            (self.hooks.debug)(&log);
+        }
+
+        let file_size = entry.file_size as usize;
+        let new_size = file_size.checked_add(data.len())
+            .filter(|&size| size <= u32::MAX as usize)
+            .ok_or(FsError::NoSpace)?;
+
+        let first_cluster = self.first_cluster_or_allocate(&mut entry)?;
+        self.write_chain(first_cluster, file_size, data)?;
+
+        entry.file_size = new_size as u32;
+        entry.set_modified(&self.now());
+        self.update_entry(entry_pos, &entry)
+    }
+
+    // The file whose short entry is at `entry_pos`, as `find_entry` and `read_dir_positions`
+    // give it. Files keep that position until they are renamed or removed.
+    pub fn file_at(&mut self, entry_pos: u64) -> Result<DirEntry, FsError> {
+        let mut raw = [0u8; 32];
+        self.read_raw_entry(entry_pos, &mut raw)?;
+
+        if raw[0] == 0x00 || raw[0] == 0xE5 || lfn::is_lfn_entry(&raw) {
+            return Err(FsError::NotFound);
+        }
+        let entry = DirEntry::deserialize(&raw);
+        if entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(entry)
+    }
+
+    // Reads from byte `offset` of the file at `entry_pos` into `buf`. Returns the number of bytes
+    // read, which is 0 at the end of the file.
+    pub fn read_at(&mut self, entry_pos: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut entry = self.file_at(entry_pos)?;
+
+        let file_size = entry.file_size as u64;
+        if offset >= file_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min((file_size - offset) as usize);
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = entry.first_cluster();
+        for _ in 0..offset / cluster_size as u64 {
+            cluster = self.read_fat_entry(cluster)?;
+        }
+
+        let mut cluster_buf = vec![0u8; cluster_size];
+        let mut in_cluster = (offset % cluster_size as u64) as usize;
+        let mut read = 0;
+
+        while read < len {
+            if !(2..0x0FFF_FFF8).contains(&cluster) {
+                // Chain ended before file_size bytes were read
+                return Err(FsError::Corrupt);
+            }
+
+            let count = (cluster_size - in_cluster).min(len - read);
+            self.read_cluster(cluster, &mut cluster_buf)?;
+            buf[read..read + count].copy_from_slice(&cluster_buf[in_cluster..in_cluster + count]);
+
+            read += count;
+            in_cluster = 0;
+            if read < len {
+                cluster = self.read_fat_entry(cluster)?;
+            }
+        }
+
+        let today = fat_date(&self.now());
+        if entry.last_access_date != today {
+            entry.last_access_date = today;
+            self.update_entry(entry_pos, &entry)?;
+        }
+
+        Ok(read)
+    }
+
+    // Writes `data` at byte `offset` of the file at `entry_pos`, growing it if needed. Writing
+    // past the end leaves a gap of zeros.
+    pub fn write_at(&mut self, entry_pos: u64, offset: u64, data: &[u8]) -> Result<usize, FsError> {
+        let mut entry = self.file_at(entry_pos)?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        let file_size = entry.file_size as u64;
+        let end = offset.checked_add(data.len() as u64)
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(FsError::NoSpace)?;
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Writing {} bytes at offset {} of the file in cluster {}", data.len(), offset, entry.first_cluster());
+            (self.hooks.debug)(&log);
+        }
+
+        let first_cluster = self.first_cluster_or_allocate(&mut entry)?;
+
+        if offset > file_size {
+            let gap = vec![0u8; (offset - file_size) as usize];
+            self.write_chain(first_cluster, file_size as usize, &gap)?;
+        }
+        self.write_chain(first_cluster, offset as usize, data)?;
+
+        entry.file_size = end.max(file_size) as u32;
+        entry.set_modified(&self.now());
+        self.update_entry(entry_pos, &entry)?;
+
+        Ok(data.len())
+    }
+
+    // Cuts the file at `entry_pos` down to `size` bytes and frees the clusters past the new end
+    pub fn truncate_at(&mut self, entry_pos: u64, size: u64) -> Result<(), FsError> {
+        let mut entry = self.file_at(entry_pos)?;
+
+        let file_size = entry.file_size as u64;
+        if size > file_size {
+            return Err(FsError::InvalidArgument);
+        }
+        if size == file_size {
+            return Ok(());
+        }
+
+        let keep = (size as usize).div_ceil(self.cluster_size());
+        let chain = self.cluster_chain(entry.first_cluster())?;
+        if chain.len() > keep {
+            match keep {
+                0 => entry.set_first_cluster(0),
+                _ => self.write_fat_entry(chain[keep - 1], 0x0FFF_FFFF)?,
+            }
+            self.free_cluster_chain(chain[keep])?;
+        }
+
+        entry.file_size = size as u32;
+        entry.set_modified(&self.now());
+        self.update_entry(entry_pos, &entry)
+    }
+
+    pub fn remove(&mut self, parent_dir_cluster: u32, filename: &str) -> Result<(), FsError> {
+        if filename == "." || filename == ".." {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let record = self.find_record(parent_dir_cluster, filename)?;
+        if record.entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Removing file {} in cluster {}", filename, record.entry.first_cluster());
+            (self.hooks.debug)(&log);
+        }
+
+        // The entry goes first, a crash in between leaves lost clusters rather than a broken file
+        self.mark_deleted(&record)?;
+        self.free_cluster_chain(record.entry.first_cluster())
+    }
+
+    // Removes an empty directory, or with `recursive` the directory and everything below it
+    pub fn remove_dir(&mut self, parent_dir_cluster: u32, dirname: &str, recursive: bool) -> Result<(), FsError> {
+        if dirname == "." || dirname == ".." {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let record = self.find_record(parent_dir_cluster, dirname)?;
+        if !record.entry.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+
+        let dir_cluster = record.entry.first_cluster();
+        if dir_cluster == self.root_dir_cluster || dir_cluster < 2 {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let has_children = self.scan_dir(dir_cluster)?
+            .iter()
+            .any(|child| !is_dot_entry(&child.entry));
+
+        if has_children {
+            if !recursive {
+                return Err(FsError::NotEmpty);
+            }
+            let mut ancestors = vec![self.root_dir_cluster, parent_dir_cluster, dir_cluster];
+            self.free_contents(dir_cluster, &mut ancestors)?;
+        }
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Removing directory {}/ in cluster {}", dirname, dir_cluster);
+            (self.hooks.debug)(&log);
+        }
+
+        self.mark_deleted(&record)?;
+        self.free_cluster_chain(dir_cluster)
+    }
+
+    // Renames `old_name` in `src_dir` to `new_name` in `dst_dir`, which may be the same directory.
+    // The data stays where it is, only the entry moves.
+    pub fn rename(&mut self, src_dir: u32, old_name: &str, dst_dir: u32, new_name: &str) -> Result<(), FsError> {
+        for name in [old_name, new_name] {
+            if name == "." || name == ".." {
+                return Err(FsError::InvalidArgument);
+            }
+        }
+        lfn::short_name_basis(new_name).ok_or(FsError::InvalidName)?;
+
+        let record = self.find_record(src_dir, old_name)?;
+        let cluster = record.entry.first_cluster();
+        let is_dir = record.entry.is_directory();
+
+        if is_dir && dst_dir != src_dir && self.is_in_subtree(dst_dir, cluster)? {
+            return Err(FsError::InvalidArgument);
+        }
+
+        // Renaming an entry to itself, e.g. only changing the case, is fine
+        let same_entry = match self.find_record(dst_dir, new_name) {
+            Ok(existing) if existing.pos == record.pos => true,
+            Ok(_) => return Err(FsError::AlreadyExists),
+            Err(FsError::NotFound) => false,
+            Err(err) => return Err(err),
+        };
+
+        if (self.hooks.debug_enabled)() {
+            let log = format!("Renaming {} in cluster {} to {} in cluster {}", old_name, src_dir, new_name, dst_dir);
+            (self.hooks.debug)(&log);
+        }
+
+        let entry = DirEntry::deserialize(&record.entry.serialize());
+
+        if same_entry {
+            // The old entry is in the way of the new one, put it back if the new one can't be written
+            self.mark_deleted(&record)?;
+            if let Err(err) = self.add_entry(dst_dir, new_name, entry) {
+                let old_long_name = record.entry.get_name().map_err(|_| FsError::Corrupt)?;
+                self.add_entry(src_dir, &old_long_name, record.entry)?;
+                return Err(err);
+            }
+        } else {
+            // The new entry is written first, a crash in between leaves two links instead of none
+            self.add_entry(dst_dir, new_name, entry)?;
+            self.mark_deleted(&record)?;
+        }
+
+        if is_dir && dst_dir != src_dir {
+            let (dot2_pos, mut dot2) = self.find_entry(cluster, "..")?;
+            dot2.set_first_cluster(self.dotdot_cluster(dst_dir));
+            self.update_entry(dot2_pos, &dot2)?;
+        }
+
+        Ok(())
+    }
+
+    // Whether `dir_cluster` is `ancestor` or somewhere below it, following ".." up to the root
+    fn is_in_subtree(&mut self, dir_cluster: u32, ancestor: u32) -> Result<bool, FsError> {
+        let mut cluster = dir_cluster;
+
+        // A directory can't be deeper than there are clusters, stop there if ".." loops
+        for _ in 0..self.cluster_count() {
+            if cluster == ancestor {
+                return Ok(true);
+            }
+            if cluster == self.root_dir_cluster || cluster < 2 {
+                return Ok(false);
+            }
+            cluster = self.find_dir_in(cluster, "..")?;
+        }
+        Err(FsError::Corrupt)
+    }
+
+    // Frees the clusters of everything inside a directory that is about to be removed. Its entries
+    // are left as they are since the directory's own clusters are freed right after.
+    fn free_contents(&mut self, dir_cluster: u32, ancestors: &mut Vec<u32>) -> Result<(), FsError> {
+        for child in self.scan_dir(dir_cluster)? {
+            if is_dot_entry(&child.entry) {
+                continue;
+            }
+
+            let cluster = child.entry.first_cluster();
+            if child.entry.is_directory() {
+                // Never follow a corrupted entry back up the tree
+                if ancestors.contains(&cluster) {
+                    continue;
+                }
+                ancestors.push(cluster);
+                let result = self.free_contents(cluster, ancestors);
+                ancestors.pop();
+                result?;
+            }
+            self.free_cluster_chain(cluster)?;
+        }
+        Ok(())
+    }
+
+    // Marks an entry and its long name entries as deleted
+    fn mark_deleted(&mut self, record: &DirRecord) -> Result<(), FsError> {
+        let mut raw = record.entry.serialize();
+        raw[0] = 0xE5;
+        self.write_raw_entry(record.pos, &raw)?;
+
+        for &pos in record.lfn_slots.iter() {
+            let mut raw = [0u8; 32];
+            self.read_raw_entry(pos, &mut raw)?;
+            raw[0] = 0xE5;
+            self.write_raw_entry(pos, &raw)?;
+        }
+        Ok(())
+    }
+
+    fn read_raw_entry(&mut self, pos: u64, raw: &mut [u8; 32]) -> Result<(), FsError> {
+        let lba = pos / SECTOR_SIZE as u64;
+        let offset = (pos % SECTOR_SIZE as u64) as usize;
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.device.read_sector(lba, &mut sector)?;
+        raw.copy_from_slice(&sector[offset..offset + 32]);
+        Ok(())
+    }
+
+    // Number of FAT entries that describe a cluster, including the two reserved ones
+    fn fat_entry_count(&self) -> usize {
+        let fat_bits = self.fat_size_sectors() * SECTOR_SIZE * 8;
+        (fat_bits / self.fat_type.entry_bits()).min(self.cluster_count() as usize + 2)
+    }
+
+    // Entries of FAT copy `fat_idx`, decoded like `read_fat_entry` does
+    fn read_fat_copy(&mut self, fat_idx: u8) -> Result<Vec<u32>, FsError> {
+        let entry_count = self.fat_entry_count();
+        let first_lba = self.fat_start as u64 + fat_idx as u64 * self.fat_size_sectors() as u64;
+
+        // Only as many sectors as the entries need. The last FAT12 entry can spill into the sector
+        // after the one it starts in, so the count goes by where that entry ends.
+        let byte_count = self.fat_type.entry_offset(entry_count as u32 - 1) + self.fat_type.entry_bytes();
+        let mut bytes = vec![0u8; byte_count.div_ceil(SECTOR_SIZE) * SECTOR_SIZE];
+        let mut sector = [0u8; SECTOR_SIZE];
+
+        for (i, chunk) in bytes.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            self.device.read_sector(first_lba + i as u64, &mut sector)?;
+            chunk.copy_from_slice(&sector);
+        }
+
+        Ok((0..entry_count as u32)
+            .map(|cluster_idx| self.fat_type.decode(cluster_idx, &bytes[self.fat_type.entry_offset(cluster_idx)..]))
+            .collect())
+    }
+
+    // Checks the volume for inconsistencies and returns what was found. With `repair` every
+    // problem is also fixed, trusting the first FAT copy and dropping whatever can't be saved.
+    pub fn check(&mut self, repair: bool) -> Result<Vec<Problem>, FsError> {
+        let mut problems = Vec::new();
+
+        // The other copies are compared against the first FAT before anything else,
+        // so the repairs below are written to copies that agree
+        let mut fat = self.read_fat_copy(0)?;
+        for fat_idx in 1..self.bpb.fat_table_count {
+            let copy = self.read_fat_copy(fat_idx)?;
+            let entries = fat.iter().zip(copy.iter()).filter(|(a, b)| a != b).count();
+
+            if entries > 0 {
+                problems.push(Problem::FatCopyMismatch { fat_idx, entries });
+
+                if repair {
+                    let fat_size = self.fat_size_sectors() as u64;
+                    let mut sector = [0u8; SECTOR_SIZE];
+                    for i in 0..fat_size {
+                        self.device.read_sector(self.fat_start as u64 + i, &mut sector)?;
+                        self.device.write_sector(self.fat_start as u64 + fat_idx as u64 * fat_size + i, &sector)?;
+                    }
+                }
+            }
+        }
+
+        let mut used = vec![false; fat.len()];
+        used[0] = true;
+        used[1] = true;
+
+        // A fixed root has no chain to follow
+        let root = self.root_dir_cluster;
+        if !self.is_fixed_root(root) {
+            if root < 2 || root as usize >= fat.len() || fat[root as usize] == 0 {
+                return Err(FsError::Corrupt);
+            }
+            self.check_chain(&mut fat, &mut used, root, "/", repair, &mut problems)?;
+        }
+
+        // Every directory is reached at most once: a subdirectory whose first cluster is
+        // already in use is reported and not descended into, which also breaks cycles
+        let mut pending = vec![(root, String::from("/"))];
+
+        while let Some((dir, path)) = pending.pop() {
+            for record in self.scan_dir(dir)? {
+                let name = record.entry.get_name().unwrap_or_default();
+                if name == "." || name == ".." {
+                    continue;
+                }
+
+                let entry_path = if path == "/" {
+                    format!("/{}", name)
+                } else {
+                    format!("{}/{}", path, name)
+                };
+
+                let first = record.entry.first_cluster();
+                let valid_start = first >= 2 && (first as usize) < fat.len() && fat[first as usize] != 0;
+
+                if first != 0 && valid_start && used[first as usize] {
+                    problems.push(Problem::CrossLinked { path: entry_path.clone(), cluster: first });
+                } else if first != 0 && !valid_start {
+                    problems.push(Problem::BrokenChain { path: entry_path.clone(), cluster: first });
+                }
+
+                let usable = valid_start && !used[first as usize];
+
+                if record.entry.is_directory() {
+                    if !usable {
+                        if first == 0 {
+                            problems.push(Problem::BrokenChain { path: entry_path.clone(), cluster: first });
+                        }
+                        if repair {
+                            self.mark_deleted(&record)?;
+                        }
+                        continue;
+                    }
+
+                    self.check_chain(&mut fat, &mut used, first, &entry_path, repair, &mut problems)?;
+                    self.check_dot_entries(first, dir, &entry_path, repair, &mut problems)?;
+
+                    pending.push((first, entry_path));
+                } else {
+                    let mut entry = record.entry;
+
+                    let chain_bytes = if usable {
+                        let len = self.check_chain(&mut fat, &mut used, first, &entry_path, repair, &mut problems)?;
+                        len as u64 * self.cluster_size() as u64
+                    } else {
+                        if first != 0 && repair {
+                            entry.set_first_cluster(0);
+                            entry.file_size = 0;
+                            self.update_entry(record.pos, &entry)?;
+                        }
+                        0
+                    };
+
+                    if (entry.file_size as u64) > chain_bytes {
+                        problems.push(Problem::SizeMismatch {
+                            path: entry_path,
+                            size: entry.file_size,
+                            chain_bytes,
+                        });
+
+                        if repair {
+                            entry.file_size = chain_bytes as u32;
+                            self.update_entry(record.pos, &entry)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Whatever is marked used but wasn't reached from the root is lost
+        let lost: Vec<u32> = (2..fat.len() as u32)
+            .filter(|&cluster| fat[cluster as usize] != 0 && !used[cluster as usize])
+            .collect();
+
+        if !lost.is_empty() {
+            problems.push(Problem::LostClusters { count: lost.len() });
+
+            if repair {
+                for &cluster in lost.iter() {
+                    self.write_fat_entry(cluster, 0)?;
+                    fat[cluster as usize] = 0;
+                }
+            }
+        }
+
+        let free = fat[2..].iter().filter(|&&entry| entry == 0).count() as u32;
+        if let Some(fs_info) = self.fs_info
+            && fs_info.free_count != FSINFO_UNKNOWN && fs_info.free_count != free {
+                problems.push(Problem::WrongFreeCount { stored: fs_info.free_count, actual: free });
+
+                if repair {
+                    if let Some(fs_info) = self.fs_info.as_mut() {
+                        fs_info.free_count = free;
+                    }
+                    self.write_fs_info()?;
+                }
+            }
+
+        Ok(problems)
+    }
+
+    // Follows the chain from `start`, which must be a valid unused cluster, and marks its clusters
+    // as used. A chain running into a free or already used cluster is cut there when repairing.
+    // Returns the number of clusters kept in the chain.
+    fn check_chain(&mut self, fat: &mut [u32], used: &mut [bool], start: u32, path: &str, repair: bool, problems: &mut Vec<Problem>) -> Result<usize, FsError> {
+        let mut cluster = start;
+        let mut len = 0;
+
+        loop {
+            used[cluster as usize] = true;
+            len += 1;
+
+            let next = fat[cluster as usize];
+            if next >= 0x0FFF_FFF8 {
+                break;
+            }
+
+            let problem = if next < 2 || next as usize >= fat.len() || fat[next as usize] == 0 {
+                Problem::BrokenChain { path: String::from(path), cluster }
+            } else if used[next as usize] {
+                Problem::CrossLinked { path: String::from(path), cluster: next }
+            } else {
+                cluster = next;
+                continue;
+            };
+
+            problems.push(problem);
+            if repair {
+                self.write_fat_entry(cluster, 0x0FFF_FFFF)?;
+                fat[cluster as usize] = 0x0FFF_FFFF;
+            }
+            break;
+        }
+        Ok(len)
+    }
+
+    // "." must point to the directory itself and ".." to its parent, or 0 when that is the root
+    fn check_dot_entries(&mut self, dir: u32, parent: u32, path: &str, repair: bool, problems: &mut Vec<Problem>) -> Result<(), FsError> {
+        let slots = self.read_dir_slots(dir)?;
+        if slots.len() < 2 {
+            return Ok(());
+        }
+
+        let parent_ok = |cluster: u32| cluster == parent || (cluster == 0 && parent == self.root_dir_cluster);
+
+        let dot = DirEntry::deserialize(&slots[0].1);
+        let dot2 = DirEntry::deserialize(&slots[1].1);
+
+        let valid = &dot.name == b".          " && dot.is_directory() && dot.first_cluster() == dir
+            && &dot2.name == b"..         " && dot2.is_directory() && parent_ok(dot2.first_cluster());
+
+        if valid {
+            return Ok(());
+        }
+        problems.push(Problem::BadDotEntry { path: String::from(path) });
+
+        // Slots that hold some other entry are left alone rather than overwritten
+        let replaceable = |raw: &[u8; 32], name: &[u8; 11]| {
+            raw[0] == 0x00 || raw[0] == 0xE5 || &raw[0..11] == name
+        };
+
+        if repair && replaceable(&slots[0].1, b".          ") && replaceable(&slots[1].1, b"..         ") {
+            self.write_raw_entry(slots[0].0, &DirEntry::new(*b".          ", dir, 0x10, &self.now()).serialize())?;
+            self.write_raw_entry(slots[1].0, &DirEntry::new(*b"..         ", self.dotdot_cluster(parent), 0x10, &self.now()).serialize())?;
+        }
+        Ok(())
+    }
+
+    pub fn new(device: &'a mut D, hooks: Hooks) -> Result<Self, FsError> {
+        let mut sector = [0u8; 512];
+        device.read_sector(0, &mut sector)?;
+
+        let bpb = BiosParameterBlock::from_bytes(&sector[0..36])?;
+
+        // All I/O goes through 512-byte sectors
+        if bpb.bytes_per_sector as usize != SECTOR_SIZE || bpb.sectors_per_cluster == 0 {
+            return Err(FsError::Corrupt);
+        }
+
+        // Only FAT32 boot records leave the 16-bit FAT size empty
+        let ebr = match bpb.fat_size_16 {
+            0 => ExtendedBootRecord32::from_bytes(&sector[36..90])?,
+            _ => ExtendedBootRecord32::from_bytes_16(&sector[36..62])?,
+        };
+
+        let fat_size = match bpb.fat_size_16 {
+            0 => ebr.fat_size_32,
+            n => n as u32,
+        };
+
+        // The sizes come from the disk, a layout that doesn't fit in 32 bits is not a volume
+        let fat_start = bpb.reserved_sectors as u32;
+        let root_dir_start = fat_size.checked_mul(bpb.fat_table_count as u32)
+            .and_then(|fats| fats.checked_add(fat_start))
+            .ok_or(FsError::Corrupt)?;
+        let root_dir_sectors = (bpb.root_entries as u32 * 32).div_ceil(SECTOR_SIZE as u32);
+        let cluster_heap_start = root_dir_start.checked_add(root_dir_sectors).ok_or(FsError::Corrupt)?;
+
+        let total_sectors = match bpb.total_sectors_16 {
+            0 => bpb.total_sectors_32,
+            n => n as u32,
+        };
+        let data_sectors = total_sectors.saturating_sub(cluster_heap_start);
+        let cluster_count = data_sectors / bpb.sectors_per_cluster as u32;
+
+        // The spec goes by the cluster count alone. A FAT32 boot record is trusted even on a volume
+        // small enough to count as FAT12, since without a fixed root it can't be read as one.
+        let fat_type = match bpb.fat_size_16 {
+            0 => FatType::Fat32,
+            _ => FatType::from_cluster_count(cluster_count),
+        };
+
+        // A FAT32 root has to be one of the clusters of the volume
+        let root_in_range = ebr.root_cluster >= 2 && ebr.root_cluster - 2 < cluster_count;
+        let root_dir_cluster = match fat_type {
+            FatType::Fat32 if root_dir_sectors == 0 && root_in_range => ebr.root_cluster,
+            FatType::Fat12 | FatType::Fat16 if root_dir_sectors > 0 => 0,
+            _ => return Err(FsError::Corrupt),
+        };
+
+        let mut fs = Self {
+            device,
+            bpb,
+            ebr,
+            fat_start,
+            fs_info: None,
+            hooks,
+            fat_type,
+            root_dir_start,
+            root_dir_sectors,
+            cluster_heap_start,
+            root_dir_cluster,
+        };
+
+        // FSInfo is only a hint, a missing or invalid one just means the FAT gets scanned
+        if ebr.fs_info != 0 && ebr.fs_info != 0xFFFF && ebr.fs_info < bpb.reserved_sectors {
+            fs.device.read_sector(ebr.fs_info as u64, &mut sector)?;
+
+            if let Ok(mut fs_info) = FsInfo::from_bytes(&sector) {
+                let cluster_count = fs.cluster_count();
+                if fs_info.free_count > cluster_count {
+                    fs_info.free_count = FSINFO_UNKNOWN;
+                }
+                if fs_info.next_free < 2 || fs_info.next_free >= cluster_count + 2 {
+                    fs_info.next_free = FSINFO_UNKNOWN;
+                }
+                fs.fs_info = Some(fs_info);
+            }
+        }
+
+        Ok(fs)
+    }
+}
+
+impl RamDisk {
+    // An unformatted disk, see `crate::fs::mkfs::format`
+    pub fn blank(size_in_sectors: usize) -> Self {
+        Self { data: vec![0u8; size_in_sectors * 512] }
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        (self.data.len() / 512) as u64
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        size_in_sectors: usize,
+        sectors_per_cluster: u8,
+        reserved_sectors: u16,
+        fat_count: u8,
+        fat_size: u32,
+        root_cluster: u32,
+        fs_info_sector: u16,
+        backup_boot_sector: u16,
+        volume_id: u32,
+        volume_label: [u8; 11],
+    ) -> Self {
+
+        let total_sectors = reserved_sectors as usize + (fat_count as usize * fat_size as usize);
+        if total_sectors > size_in_sectors {
+            panic!(
+                "Provided size_in_sectors ({}) is too small; at least {} sectors are required to fit reserved + FAT + data regions.",
+                size_in_sectors, total_sectors
+            );        
+        }
+        let mut data = vec![0u8; size_in_sectors * 512];
+
+        // Basic jump and OEM
+        data[0] = 0xEB;
+        data[1] = 0x58;
+        data[2] = 0x90;
+        data[3..11].copy_from_slice(b"MSWIN4.1");
+
+        // BIOS Parameter Block
+        data[11..13].copy_from_slice(&512u16.to_le_bytes()); // bytes/sector
+        data[13] = sectors_per_cluster;
+        data[14..16].copy_from_slice(&reserved_sectors.to_le_bytes());
+        data[16] = fat_count;
+        data[17..19].copy_from_slice(&0u16.to_le_bytes()); // root entries
+
+        data[19..21].copy_from_slice(&(if size_in_sectors < 65536 {
+            size_in_sectors as u16} else {0}).to_le_bytes());
+
+        data[21] = 0xF8; // media descriptor
+        data[22..24].copy_from_slice(&0u16.to_le_bytes()); // fat_size_16
+        data[24..26].copy_from_slice(&63u16.to_le_bytes()); // sectors/track
+        data[26..28].copy_from_slice(&255u16.to_le_bytes()); // heads
+        data[28..32].copy_from_slice(&0u32.to_le_bytes()); // hidden sectors
+        data[32..36].copy_from_slice(&(if size_in_sectors >= 65536 {
+            size_in_sectors as u32
+        } else {
+                0
+            })
+            .to_le_bytes());
+
+        // Extended Boot Record
+        data[36..40].copy_from_slice(&fat_size.to_le_bytes());
+        data[40..42].copy_from_slice(&0u16.to_le_bytes()); // ext flags
+        data[42..44].copy_from_slice(&0u16.to_le_bytes()); // fat version
+        data[44..48].copy_from_slice(&root_cluster.to_le_bytes());
+        data[48..50].copy_from_slice(&fs_info_sector.to_le_bytes());
+        data[50..52].copy_from_slice(&backup_boot_sector.to_le_bytes());
+
+        // Drive/boot fields
+        data[64] = 0x80;
+        data[65] = 0x00; // Windows NT flags
+        data[66] = 0x29; // signature
+        data[67..71].copy_from_slice(&volume_id.to_le_bytes());
+        data[71..82].copy_from_slice(&volume_label);
+        data[82..90].copy_from_slice(b"FAT32   ");
+
+        // Boot sector signature
+        data[510] = 0x55;
+        data[511] = 0xAA;
+
+        // FSInfo, the free count is unknown until the FATs are set up
+        let fs_info = FsInfo {
+            free_count: FSINFO_UNKNOWN,
+            next_free: FSINFO_UNKNOWN,
+        };
+        let fs_info_start = fs_info_sector as usize * 512;
+        data[fs_info_start..fs_info_start + 512].copy_from_slice(&fs_info.serialize());
+
+        Self { data }
+    }
+}
+
+
+impl BiosParameterBlock {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, FsError> {
+        if buf.len() < 36 {
+            return Err(FsError::Corrupt);
+        }
+        Ok(Self {
+            _jmp: [buf[0], buf[1], buf[2]],
+            _oem: buf[3..11].try_into().unwrap(),
+
+            bytes_per_sector: u16::from_le_bytes(buf[11..13].try_into().unwrap()),
+            sectors_per_cluster: buf[13],
+            reserved_sectors: u16::from_le_bytes(buf[14..16].try_into().unwrap()),
+            fat_table_count: buf[16],
+            root_entries: u16::from_le_bytes(buf[17..19].try_into().unwrap()),
+            total_sectors_16: u16::from_le_bytes(buf[19..21].try_into().unwrap()),
+            media_descriptor: buf[21],
+            fat_size_16: u16::from_le_bytes(buf[22..24].try_into().unwrap()),
+            sectors_per_track: u16::from_le_bytes(buf[24..26].try_into().unwrap()),
+            heads_on_media: u16::from_le_bytes(buf[26..28].try_into().unwrap()),
+            hidden_sectors: u32::from_le_bytes(buf[28..32].try_into().unwrap()),
+            total_sectors_32: u32::from_le_bytes(buf[32..36].try_into().unwrap()),
+        })
+    }
+
+    pub fn serialize(&self) -> [u8; 36] {
+        let mut buffer = [0u8; 36];
+
+        buffer[0..3].copy_from_slice(&self._jmp);
+        buffer[3..11].copy_from_slice(&self._oem);
+        buffer[11..13].copy_from_slice(&self.bytes_per_sector.to_le_bytes());
+        buffer[13] = self.sectors_per_cluster;
+        buffer[14..16].copy_from_slice(&self.reserved_sectors.to_le_bytes());
+        buffer[16] = self.fat_table_count;
+        buffer[17..19].copy_from_slice(&self.root_entries.to_le_bytes());
+        buffer[19..21].copy_from_slice(&self.total_sectors_16.to_le_bytes());
+        buffer[21] = self.media_descriptor;
+        buffer[22..24].copy_from_slice(&self.fat_size_16.to_le_bytes());
+        buffer[24..26].copy_from_slice(&self.sectors_per_track.to_le_bytes());
+        buffer[26..28].copy_from_slice(&self.heads_on_media.to_le_bytes());
+        buffer[28..32].copy_from_slice(&self.hidden_sectors.to_le_bytes());
+        buffer[32..36].copy_from_slice(&self.total_sectors_32.to_le_bytes());
+
+        buffer
+    }
+}
+
+impl FsInfo {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, FsError> {
+        if buf.len() < 512 {
+            return Err(FsError::Corrupt);
+        }
+
+        let lead_sig = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let struct_sig = u32::from_le_bytes(buf[484..488].try_into().unwrap());
+        let trail_sig = u32::from_le_bytes(buf[508..512].try_into().unwrap());
+
+        if lead_sig != FSINFO_LEAD_SIG || struct_sig != FSINFO_STRUCT_SIG || trail_sig != FSINFO_TRAIL_SIG {
+            return Err(FsError::Corrupt);
+        }
+
+        Ok(Self {
+            free_count: u32::from_le_bytes(buf[488..492].try_into().unwrap()),
+            next_free: u32::from_le_bytes(buf[492..496].try_into().unwrap()),
+        })
+    }
+
+    pub fn serialize(&self) -> [u8; 512] {
+        let mut buffer = [0u8; 512];
+
+        buffer[0..4].copy_from_slice(&FSINFO_LEAD_SIG.to_le_bytes());
+        buffer[484..488].copy_from_slice(&FSINFO_STRUCT_SIG.to_le_bytes());
+        buffer[488..492].copy_from_slice(&self.free_count.to_le_bytes());
+        buffer[492..496].copy_from_slice(&self.next_free.to_le_bytes());
+        buffer[508..512].copy_from_slice(&FSINFO_TRAIL_SIG.to_le_bytes());
+
+        buffer
+    }
+}
+
+impl FatType {
+    // Fewer than 4085 clusters is FAT12 and fewer than 65525 is FAT16
+    pub fn from_cluster_count(cluster_count: u32) -> Self {
+        if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn entry_bits(self) -> usize {
+        match self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        }
+    }
+
+    // Bytes that hold an entry, a FAT12 one shares the middle byte with its neighbour
+    fn entry_bytes(self) -> usize {
+        match self {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    // Offset of the entry of `cluster_idx` from the start of the FAT
+    fn entry_offset(self, cluster_idx: u32) -> usize {
+        let cluster_idx = cluster_idx as usize;
+        match self {
+            FatType::Fat12 => cluster_idx + cluster_idx / 2,
+            FatType::Fat16 => cluster_idx * 2,
+            FatType::Fat32 => cluster_idx * 4,
+        }
+    }
+
+    // The entry in the bytes starting at its offset. Bad cluster and end of chain marks are
+    // widened to their FAT32 values so the rest of the code only has to know those.
+    fn decode(self, cluster_idx: u32, bytes: &[u8]) -> u32 {
+        match self {
+            FatType::Fat12 => {
+                let pair = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+                let entry = if cluster_idx.is_multiple_of(2) { pair & 0x0FFF } else { pair >> 4 };
+                if entry >= 0x0FF7 { entry | 0x0FFF_F000 } else { entry }
+            }
+            FatType::Fat16 => {
+                let entry = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+                if entry >= 0xFFF7 { entry | 0x0FFF_0000 } else { entry }
+            }
+            FatType::Fat32 => u32::from_le_bytes(bytes[0..4].try_into().unwrap()) & 0x0FFF_FFFF,
+        }
+    }
+
+    // Stores `value` in the bytes starting at the entry's offset, keeping the nibble a FAT12
+    // entry shares with its neighbour and the reserved top 4 bits of a FAT32 entry
+    fn encode(self, cluster_idx: u32, value: u32, bytes: &mut [u8]) {
+        match self {
+            FatType::Fat12 => {
+                let old = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let value = (value & 0x0FFF) as u16;
+                let new = if cluster_idx.is_multiple_of(2) {
+                    (old & 0xF000) | value
+                } else {
+                    (old & 0x000F) | (value << 4)
+                };
+                bytes[0..2].copy_from_slice(&new.to_le_bytes());
+            }
+            FatType::Fat16 => bytes[0..2].copy_from_slice(&(value as u16).to_le_bytes()),
+            FatType::Fat32 => {
+                let old = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                let new = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                bytes[0..4].copy_from_slice(&new.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl ExtendedBootRecord32 {
+    // The FAT12/FAT16 boot record only has the fields from the drive number on. The FAT32 ones
+    // are left empty: the FAT size is in the BPB and there is no root cluster or FSInfo.
+    pub fn from_bytes_16(buf: &[u8]) -> Result<Self, FsError> {
+        if buf.len() < 26 {
+            return Err(FsError::Corrupt);
+        }
+        Ok(Self {
+            fat_size_32: 0,
+            ext_flags: 0,
+            fat_version: 0,
+            root_cluster: 0,
+            fs_info: 0,
+            backup_boot: 0,
+            _reserved: [0; 12],
+            drive_number: buf[0],
+            _win_nt_flags: buf[1],
+            signature: buf[2],
+            volume_id: u32::from_le_bytes(buf[3..7].try_into().unwrap()),
+            volume_label: buf[7..18].try_into().unwrap(),
+            fs_id: buf[18..26].try_into().unwrap(),
+        })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, FsError> {
+        if buf.len() < 54 {
+            return Err(FsError::Corrupt);
+        }
+        Ok(Self {
+            fat_size_32: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            ext_flags: u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+            fat_version: u16::from_le_bytes(buf[6..8].try_into().unwrap()),
+            root_cluster: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
+            fs_info: u16::from_le_bytes(buf[12..14].try_into().unwrap()),
+            backup_boot: u16::from_le_bytes(buf[14..16].try_into().unwrap()),
+            _reserved: buf[16..28].try_into().unwrap(),
+            drive_number: buf[28],
+            _win_nt_flags: buf[29],
+            signature: buf[30],
+            volume_id: u32::from_le_bytes(buf[31..35].try_into().unwrap()),
+            volume_label: buf[35..46].try_into().unwrap(),
+            fs_id: buf[46..54].try_into().unwrap(),
+        })
+    }
+
+    pub fn serialize(&self) -> [u8; 54] {
+        let mut buffer = [0u8; 54];
+
+        buffer[0..4].copy_from_slice(&self.fat_size_32.to_le_bytes());
+        buffer[4..6].copy_from_slice(&self.ext_flags.to_le_bytes());
+        buffer[6..8].copy_from_slice(&self.fat_version.to_le_bytes());
+        buffer[8..12].copy_from_slice(&self.root_cluster.to_le_bytes());
+        buffer[12..14].copy_from_slice(&self.fs_info.to_le_bytes());
+        buffer[14..16].copy_from_slice(&self.backup_boot.to_le_bytes());
+        buffer[16..28].copy_from_slice(&self._reserved);
+        buffer[28] = self.drive_number;
+        buffer[29] = self._win_nt_flags;
+        buffer[30] = self.signature;
+        buffer[31..35].copy_from_slice(&self.volume_id.to_le_bytes());
+        buffer[35..46].copy_from_slice(&self.volume_label);
+        buffer[46..54].copy_from_slice(&self.fs_id);
+
+        buffer
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        let start = (lba as usize) * 512;
+        let end = start + 512;
+        if end > self.data.len() {
+            return Err(FsError::Io);
+        }
+        buf.copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        let start = (lba as usize) * 512;
+        let end = start + 512;
+        if end > self.data.len() {
+            return Err(FsError::Io);
+        }
+        self.data[start..end].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl fmt::Display for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cluster = ((self.first_cluster_high as u32) << 16) | (self.first_cluster_low as u32);
+
+        write!(
+            f,"DirEntry {{
+                Name: \"{}\", FC: {}
+                }}", self.get_name().unwrap(), cluster
+        )
+    }
+}
+
+impl fmt::Display for BiosParameterBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let oem_str = core::str::from_utf8(&self._oem)
+            .unwrap_or("<invalid utf8>");
+
+        write!(
+            f,
+            "BiosParameterBlock {{
+JMP: {:02X?}
+OEM: {}
+Bytes per sector: {}
+Sectors per cluster: {}
+Reserved sectors: {}
+FAT table count: {}
+Root entries: {}
+Total sectors 16: {}
+Media descriptor: 0x{:02X}
+FAT size 16: {}
+Sectors per track: {}
+Heads on media: {}
+Hidden sectors: {}
+Total sectors 32: {}
+}}",
+            &self._jmp,
+            oem_str,
+            self.bytes_per_sector,
+            self.sectors_per_cluster,
+            self.reserved_sectors,
+            self.fat_table_count,
+            self.root_entries,
+            self.total_sectors_16,
+            self.media_descriptor,
+            self.fat_size_16,
+            self.sectors_per_track,
+            self.heads_on_media,
+            self.hidden_sectors,
+            self.total_sectors_32,
+        )
+    }
+}
+
+impl fmt::Display for ExtendedBootRecord32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let volume_label_str = core::str::from_utf8(&self.volume_label)
+            .unwrap_or("<invalid utf8>");
+        let fs_id_str = core::str::from_utf8(&self.fs_id)
+            .unwrap_or("<invalid utf8>");
+
+        write!(
+            f,
+            "ExtendedBootRecord32 {{
+FAT size 32: {}
+Ext flags: {}
+FAT version: {}
+Root cluster: {}
+FS info sector: {}
+Backup boot sector: {}
+Drive number: {}
+Signature: 0x{:02X}
+Volume ID: 0x{:08X}
+Volume Label: {}
+FS ID: {}
+}}",
+            self.fat_size_32,
+            self.ext_flags,
+            self.fat_version,
+            self.root_cluster,
+            self.fs_info,
+            self.backup_boot,
+            self.drive_number,
+            self.signature,
+            self.volume_id,
+            volume_label_str,
+            fs_id_str,
+        )
+    }
+}
+
+impl fmt::Display for FatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FatType::Fat12 => write!(f, "FAT12"),
+            FatType::Fat16 => write!(f, "FAT16"),
+            FatType::Fat32 => write!(f, "FAT32"),
+        }
+    }
+}
+
+impl fmt::Display for FsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FsInfo {{
+Free clusters: {}
+Next free cluster: {}
+}}",
+            self.free_count,
+            self.next_free,
+        )
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::FatCopyMismatch { fat_idx, entries } => {
+                write!(f, "FAT copy {} differs from the first FAT in {} entries", fat_idx, entries)
+            }
+            Problem::BrokenChain { path, cluster } => {
+                write!(f, "{}: cluster chain is broken at cluster {}", path, cluster)
+            }
+            Problem::CrossLinked { path, cluster } => {
+                write!(f, "{}: cluster {} is used more than once", path, cluster)
+            }
+            Problem::BadDotEntry { path } => {
+                write!(f, "{}: bad '.' or '..' entry", path)
+            }
+            Problem::SizeMismatch { path, size, chain_bytes } => {
+                write!(f, "{}: size is {} bytes but its clusters only hold {}", path, size, chain_bytes)
+            }
+            Problem::LostClusters { count } => {
+                write!(f, "{} clusters are marked used but not reachable", count)
+            }
+            Problem::WrongFreeCount { stored, actual } => {
+                write!(f, "FSInfo says {} clusters are free, the FAT has {}", stored, actual)
+            }
+        }
+    }
+}
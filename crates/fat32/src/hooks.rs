@@ -0,0 +1,52 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+// What the file system needs from whoever runs it. The kernel prints to the screen and reads the
+// RTC, a host program can log to stderr and use the system clock.
+#[derive(Clone, Copy)]
+pub struct Hooks {
+    // Output meant for the user, like the tree from `FileSystem::print_tree`
+    pub log: fn(&str),
+    // Called for each step of an operation while `debug_enabled` returns true
+    pub debug: fn(&str),
+    pub debug_enabled: fn() -> bool,
+    // Time stamped on the entries that are created or modified
+    pub now: fn() -> DateTime,
+    // Serial number of the volumes `mkfs::format` creates, which should differ between them
+    pub volume_id: fn() -> u32,
+}
+
+// The FAT epoch, the earliest date an entry can hold
+const EPOCH: DateTime = DateTime { year: 1980, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+
+// Nothing is logged, every timestamp is the FAT epoch and every volume has the same serial
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            log: |_| {},
+            debug: |_| {},
+            debug_enabled: || false,
+            now: || EPOCH,
+            volume_id: || 0x1980_0101,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
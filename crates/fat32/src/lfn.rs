@@ -0,0 +1,242 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const LFN_ATTR: u8 = 0x0F;
+pub const LAST_LFN_ENTRY: u8 = 0x40;
+
+const CHARS_PER_ENTRY: usize = 13;
+const MAX_NAME_LEN: usize = 255;
+
+// Byte offsets of the 13 UCS-2 characters stored in a long name entry
+const CHAR_OFFSETS: [usize; CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+pub fn is_lfn_entry(raw: &[u8; 32]) -> bool {
+    raw[11] & 0x3F == LFN_ATTR
+}
+
+pub fn checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+// Long name entries for `name`, in the order they are stored on disk (last fragment first)
+pub fn long_name_entries(name: &str, short_name: &[u8; 11]) -> Option<Vec<[u8; 32]>> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    if units.is_empty() || units.len() > MAX_NAME_LEN {
+        return None;
+    }
+
+    let count = units.len().div_ceil(CHARS_PER_ENTRY);
+    let checksum = checksum(short_name);
+
+    let mut entries = Vec::with_capacity(count);
+
+    for ord in (1..=count).rev() {
+        let mut raw = [0u8; 32];
+
+        raw[0] = ord as u8;
+        if ord == count {
+            raw[0] |= LAST_LFN_ENTRY;
+        }
+        raw[11] = LFN_ATTR;
+        raw[13] = checksum;
+
+        for (i, &pos) in CHAR_OFFSETS.iter().enumerate() {
+            let idx = (ord - 1) * CHARS_PER_ENTRY + i;
+
+            // Name is null terminated when it doesn't fill the entry, the rest is 0xFFFF padding
+            let unit = if idx < units.len() {
+                units[idx]
+            } else if idx == units.len() {
+                0x0000
+            } else {
+                0xFFFF
+            };
+            raw[pos..pos + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+
+        entries.push(raw);
+    }
+
+    Some(entries)
+}
+
+// Collects the long name entries that precede a short entry while a directory is scanned
+pub struct LongNameBuilder {
+    units: Vec<u16>,
+    checksum: u8,
+    next_ord: u8,
+    valid: bool,
+}
+
+impl Default for LongNameBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LongNameBuilder {
+    pub fn new() -> Self {
+        Self {
+            units: Vec::new(),
+            checksum: 0,
+            next_ord: 0,
+            valid: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.units.clear();
+        self.next_ord = 0;
+        self.valid = false;
+    }
+
+    pub fn push(&mut self, raw: &[u8; 32]) {
+        let ord = raw[0] & !LAST_LFN_ENTRY;
+
+        if raw[0] & LAST_LFN_ENTRY != 0 {
+            if ord == 0 || ord as usize * CHARS_PER_ENTRY > MAX_NAME_LEN + CHARS_PER_ENTRY {
+                self.reset();
+                return;
+            }
+            self.units = vec![0xFFFF; ord as usize * CHARS_PER_ENTRY];
+            self.checksum = raw[13];
+            self.next_ord = ord;
+            self.valid = true;
+        } else if !self.valid || ord != self.next_ord || raw[13] != self.checksum {
+            self.reset();
+            return;
+        }
+
+        let base = (ord as usize - 1) * CHARS_PER_ENTRY;
+        for (i, &pos) in CHAR_OFFSETS.iter().enumerate() {
+            self.units[base + i] = u16::from_le_bytes([raw[pos], raw[pos + 1]]);
+        }
+
+        self.next_ord -= 1;
+    }
+
+    // The long name, if a complete sequence belonging to `short_name` was collected
+    pub fn finish(&mut self, short_name: &[u8; 11]) -> Option<String> {
+        let complete = self.valid && self.next_ord == 0 && self.checksum == checksum(short_name);
+
+        let name = if complete {
+            let len = self.units.iter().position(|&u| u == 0x0000 || u == 0xFFFF)
+                .unwrap_or(self.units.len());
+            String::from_utf16(&self.units[..len]).ok().filter(|name| !name.is_empty())
+        } else {
+            None
+        };
+
+        self.reset();
+        name
+    }
+}
+
+fn is_valid_long_char(c: char) -> bool {
+    !c.is_control() && !matches!(c, '"' | '*' | '/' | ':' | '<' | '>' | '?' | '\\' | '|')
+}
+
+fn is_valid_short_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+// Uppercase 8.3 basis name for `name`, and whether the conversion lost information.
+// Returns None if `name` can't be used as a file name at all.
+pub fn short_name_basis(name: &str) -> Option<([u8; 11], bool)> {
+    if name == "." || name == ".." || name.trim().is_empty() {
+        return None;
+    }
+    if name.encode_utf16().count() > MAX_NAME_LEN || !name.chars().all(is_valid_long_char) {
+        return None;
+    }
+
+    let mut lossy = false;
+
+    let stripped = name.trim_start_matches('.');
+    if stripped.len() != name.len() {
+        lossy = true;
+    }
+
+    let (base, ext) = match stripped.rfind('.') {
+        Some(idx) => (&stripped[..idx], &stripped[idx + 1..]),
+        None => (stripped, ""),
+    };
+
+    let mut short = [b' '; 11];
+
+    let mut fill = |part: &str, out: &mut [u8]| {
+        let mut len = 0;
+        for c in part.chars() {
+            if c == ' ' || c == '.' {
+                lossy = true;
+                continue;
+            }
+            if len == out.len() {
+                lossy = true;
+                break;
+            }
+
+            out[len] = if is_valid_short_char(c) {
+                c.to_ascii_uppercase() as u8
+            } else {
+                lossy = true;
+                b'_'
+            };
+            len += 1;
+        }
+        len
+    };
+
+    let base_len = fill(base, &mut short[..8]);
+    fill(ext, &mut short[8..]);
+
+    if base_len == 0 {
+        short[0] = b'_';
+        lossy = true;
+    }
+
+    Some((short, lossy))
+}
+
+// Basis name with a "~n" tail, e.g. LONGFI~1.TXT
+pub fn with_numeric_tail(basis: &[u8; 11], n: u32) -> [u8; 11] {
+    let tail = format!("~{}", n);
+    let base_len = basis[..8].iter().position(|&b| b == b' ').unwrap_or(8);
+    let keep = base_len.min(8 - tail.len());
+
+    let mut short = [b' '; 11];
+    short[..keep].copy_from_slice(&basis[..keep]);
+    short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+    short[8..].copy_from_slice(&basis[8..]);
+
+    short
+}
+
+// "HELLO   TXT" -> "HELLO.TXT"
+pub fn short_name_to_string(short_name: &[u8; 11]) -> String {
+    let (name_bytes, ext_bytes) = short_name.split_at(8);
+
+    let mut out = String::new();
+    for &b in name_bytes.iter() {
+        out.push(b as char);
+    }
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+
+    let ext: String = ext_bytes.iter().map(|&b| b as char).collect();
+    let ext = ext.trim_end();
+    if !ext.is_empty() {
+        out.push('.');
+        out.push_str(ext);
+    }
+    out
+}
+
+// A long name is only needed when the short name can't reproduce `name` exactly
+pub fn needs_long_name(name: &str, short_name: &[u8; 11]) -> bool {
+    short_name_to_string(short_name) != name
+}
@@ -0,0 +1,17 @@
+#![no_std]
+
+// FAT12, FAT16 and FAT32 on any `BlockDevice`. Only `alloc` is needed, so the same code runs in
+// the kernel and in host tests; whatever else it needs from its environment comes in `Hooks`.
+
+extern crate alloc;
+
+pub mod error;
+pub mod lfn;
+pub mod mkfs;
+
+mod filesystem;
+mod hooks;
+mod path;
+
+pub use filesystem::*;
+pub use hooks::{DateTime, Hooks};
@@ -0,0 +1,159 @@
+use crate::error::FsError;
+use crate::{BiosParameterBlock, BlockDevice, DirEntry, ExtendedBootRecord32, FsInfo, Hooks};
+
+const SECTOR_SIZE: usize = 512;
+
+const RESERVED_SECTORS: u16 = 32;
+const FAT_COUNT: u8 = 2;
+const ROOT_CLUSTER: u32 = 2;
+const FS_INFO_SECTOR: u16 = 1;
+const BACKUP_BOOT_SECTOR: u16 = 6;
+
+// Fewer clusters than this and the volume would be FAT16 by definition
+const MIN_CLUSTERS: u32 = 65525;
+const MAX_CLUSTERS: u32 = 0x0FFF_FFF5;
+
+// Sectors per cluster by volume size in sectors, from the FAT32 table of the Microsoft spec.
+// Volumes up to 66600 sectors are too small for FAT32.
+const CLUSTER_SIZE_TABLE: [(u32, u8); 6] = [
+    (66600, 0),
+    (532480, 1),
+    (16777216, 8),
+    (33554432, 16),
+    (67108864, 32),
+    (0xFFFF_FFFF, 64),
+];
+
+fn sectors_per_cluster(total_sectors: u32) -> Option<u8> {
+    CLUSTER_SIZE_TABLE.iter()
+        .find(|&&(max_sectors, _)| total_sectors <= max_sectors)
+        .map(|&(_, sectors_per_cluster)| sectors_per_cluster)
+        .filter(|&sectors_per_cluster| sectors_per_cluster != 0)
+}
+
+// FAT size in sectors, computed the way the spec does. It can be slightly larger than needed.
+fn fat_size(total_sectors: u32, sectors_per_cluster: u8) -> u32 {
+    let tmp1 = total_sectors - RESERVED_SECTORS as u32;
+    let tmp2 = (256 * sectors_per_cluster as u32 + FAT_COUNT as u32) / 2;
+    tmp1.div_ceil(tmp2)
+}
+
+fn volume_label(label: Option<&str>) -> Result<[u8; 11], FsError> {
+    let label = label.unwrap_or("NO NAME");
+
+    let valid = label.bytes().all(|b| {
+        b == b' ' || (b.is_ascii_graphic() && !b"\"*+,./:;<=>?[\\]|".contains(&b))
+    });
+    if label.is_empty() || label.len() > 11 || !valid {
+        return Err(FsError::InvalidName);
+    }
+
+    let mut out = [b' '; 11];
+    for (dst, src) in out.iter_mut().zip(label.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+    Ok(out)
+}
+
+// Writes an empty FAT32 file system on the first `total_sectors` sectors of `device`
+pub fn format<D: BlockDevice>(device: &mut D, total_sectors: u64, label: Option<&str>, hooks: Hooks) -> Result<(), FsError> {
+    let total_sectors = u32::try_from(total_sectors).map_err(|_| FsError::InvalidSize)?;
+    let sectors_per_cluster = sectors_per_cluster(total_sectors).ok_or(FsError::InvalidSize)?;
+    let label_bytes = volume_label(label)?;
+
+    let fat_size = fat_size(total_sectors, sectors_per_cluster);
+    let data_start = RESERVED_SECTORS as u32 + FAT_COUNT as u32 * fat_size;
+    let cluster_count = (total_sectors - data_start) / sectors_per_cluster as u32;
+
+    if !(MIN_CLUSTERS..=MAX_CLUSTERS).contains(&cluster_count) {
+        return Err(FsError::InvalidSize);
+    }
+
+    let bpb = BiosParameterBlock {
+        _jmp: [0xEB, 0x58, 0x90],
+        _oem: *b"MSWIN4.1",
+        bytes_per_sector: SECTOR_SIZE as u16,
+        sectors_per_cluster,
+        reserved_sectors: RESERVED_SECTORS,
+        fat_table_count: FAT_COUNT,
+        root_entries: 0,
+        total_sectors_16: 0,
+        media_descriptor: 0xF8,
+        fat_size_16: 0,
+        sectors_per_track: 63,
+        heads_on_media: 255,
+        hidden_sectors: 0,
+        total_sectors_32: total_sectors,
+    };
+
+    let ebr = ExtendedBootRecord32 {
+        fat_size_32: fat_size,
+        ext_flags: 0,
+        fat_version: 0,
+        root_cluster: ROOT_CLUSTER,
+        fs_info: FS_INFO_SECTOR,
+        backup_boot: BACKUP_BOOT_SECTOR,
+        _reserved: [0; 12],
+        drive_number: 0x80,
+        _win_nt_flags: 0,
+        signature: 0x29,
+        volume_id: (hooks.volume_id)(),
+        volume_label: label_bytes,
+        fs_id: *b"FAT32   ",
+    };
+
+    let mut boot_sector = [0u8; SECTOR_SIZE];
+    boot_sector[0..36].copy_from_slice(&bpb.serialize());
+    boot_sector[36..90].copy_from_slice(&ebr.serialize());
+    boot_sector[510] = 0x55;
+    boot_sector[511] = 0xAA;
+
+    // The root directory takes the first cluster
+    let fs_info = FsInfo {
+        free_count: cluster_count - 1,
+        next_free: ROOT_CLUSTER + 1,
+    }.serialize();
+
+    let empty_sector = [0u8; SECTOR_SIZE];
+
+    // Reserved region, the backup boot sector is followed by a backup of FSInfo
+    let backup_boot = BACKUP_BOOT_SECTOR as u64;
+    for sector in 0..RESERVED_SECTORS as u64 {
+        let data = if sector == 0 || sector == backup_boot {
+            &boot_sector
+        } else if sector == FS_INFO_SECTOR as u64 || sector == backup_boot + 1 {
+            &fs_info
+        } else {
+            &empty_sector
+        };
+        device.write_sector(sector, data)?;
+    }
+
+    // Entry 0 holds the media descriptor, entry 1 and the root directory are end of chain
+    let mut first_fat_sector = [0u8; SECTOR_SIZE];
+    first_fat_sector[0..4].copy_from_slice(&0x0FFF_FFF8u32.to_le_bytes());
+    first_fat_sector[4..8].copy_from_slice(&0x0FFF_FFFFu32.to_le_bytes());
+    first_fat_sector[8..12].copy_from_slice(&0x0FFF_FFFFu32.to_le_bytes());
+
+    for fat_idx in 0..FAT_COUNT as u64 {
+        let fat_lba = RESERVED_SECTORS as u64 + fat_idx * fat_size as u64;
+
+        device.write_sector(fat_lba, &first_fat_sector)?;
+        for sector in 1..fat_size as u64 {
+            device.write_sector(fat_lba + sector, &empty_sector)?;
+        }
+    }
+
+    // The root directory only holds the volume label, it has no "." or ".." entries
+    let mut root_sector = [0u8; SECTOR_SIZE];
+    if label.is_some() {
+        root_sector[0..32].copy_from_slice(&DirEntry::new(label_bytes, 0, 0x08, &(hooks.now)()).serialize());
+    }
+
+    device.write_sector(data_start as u64, &root_sector)?;
+    for sector in 1..sectors_per_cluster as u64 {
+        device.write_sector(data_start as u64 + sector, &empty_sector)?;
+    }
+
+    Ok(())
+}
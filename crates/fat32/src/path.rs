@@ -0,0 +1,44 @@
+use crate::error::FsError;
+use crate::{BlockDevice, FileSystem};
+
+impl<'a, D: BlockDevice> FileSystem<'a, D> {
+    // Cluster of the directory `path` names. Relative paths start at `cwd`, absolute ones at the root.
+    pub fn resolve_dir(&mut self, cwd: u32, path: &str) -> Result<u32, FsError> {
+        let mut cluster = if path.starts_with('/') { self.root_cluster() } else { cwd };
+
+        for component in path.split('/') {
+            cluster = match component {
+                "" | "." => cluster,
+                // The root is its own parent
+                ".." if cluster == self.root_cluster() => cluster,
+                name => self.find_dir_in(cluster, name)?,
+            };
+
+            // ".." is 0 in directories right below the root
+            if cluster == 0 {
+                cluster = self.root_cluster();
+            }
+        }
+
+        Ok(cluster)
+    }
+
+    // Splits `path` into the cluster of the directory that holds its last component and that
+    // component, e.g. "/a/b/c" gives the cluster of "/a/b" and "c"
+    pub fn resolve<'p>(&mut self, cwd: u32, path: &'p str) -> Result<(u32, &'p str), FsError> {
+        let trimmed = path.trim_end_matches('/');
+
+        let (parent, leaf) = match trimmed.rfind('/') {
+            Some(0) => ("/", &trimmed[1..]),
+            Some(idx) => (&trimmed[..idx], &trimmed[idx + 1..]),
+            None => ("", trimmed),
+        };
+
+        // Nothing is left of "/" once the slashes are gone
+        if leaf.is_empty() {
+            return Err(FsError::InvalidArgument);
+        }
+
+        Ok((self.resolve_dir(cwd, parent)?, leaf))
+    }
+}
@@ -0,0 +1,85 @@
+#![allow(dead_code)]
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use fat32::error::FsError;
+use fat32::BlockDevice;
+
+// A disk image in the temporary directory, removed when the test is done with it
+pub struct Image {
+    pub path: PathBuf,
+}
+
+impl Image {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("fat32-{}-{}.img", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        Self { path }
+    }
+
+    // A zeroed image of `sectors` sectors
+    pub fn blank(name: &str, sectors: u64) -> Self {
+        let image = Self::new(name);
+        File::create(&image.path).unwrap().set_len(sectors * 512).unwrap();
+        image
+    }
+
+    pub fn open(&self) -> ImageFile {
+        ImageFile(OpenOptions::new().read(true).write(true).open(&self.path).unwrap())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct ImageFile(File);
+
+impl BlockDevice for ImageFile {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        self.0.seek(SeekFrom::Start(lba * 512)).map_err(|_| FsError::Io)?;
+        self.0.read_exact(buf).map_err(|_| FsError::Io)
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        self.0.seek(SeekFrom::Start(lba * 512)).map_err(|_| FsError::Io)?;
+        self.0.write_all(buf).map_err(|_| FsError::Io)
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        self.0.sync_all().map_err(|_| FsError::Io)
+    }
+}
+
+// Makes a FAT12, FAT16 or FAT32 image of `size_kb` KiB with mkfs.fat
+pub fn mkfs_fat(name: &str, fat_bits: u8, size_kb: u64, label: &str) -> Image {
+    let image = Image::new(name);
+    let output = Command::new("mkfs.fat")
+        .args(["-C", "-F", &fat_bits.to_string(), "-n", label])
+        .arg(&image.path)
+        .arg(size_kb.to_string())
+        .output()
+        .expect("mkfs.fat from dosfstools is needed");
+    assert!(output.status.success(), "mkfs.fat failed: {}", String::from_utf8_lossy(&output.stderr));
+    image
+}
+
+// Runs fsck.fat without letting it change anything and fails the test if it finds a problem
+pub fn fsck_fat(image: &Image) {
+    let output = Command::new("fsck.fat")
+        .arg("-n")
+        .arg(&image.path)
+        .output()
+        .expect("fsck.fat from dosfstools is needed");
+    assert!(
+        output.status.success(),
+        "fsck.fat found problems:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+}
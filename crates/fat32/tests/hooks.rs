@@ -0,0 +1,72 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Mutex;
+
+use fat32::{fat_date, fat_time, mkfs, BlockDevice, DateTime, FileSystem, Hooks, RamDisk};
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static LOG: Mutex<String> = Mutex::new(String::new());
+
+const NOW: DateTime = DateTime { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 58 };
+
+fn hooks() -> Hooks {
+    Hooks {
+        log: |text| LOG.lock().unwrap().push_str(text),
+        debug: |message| MESSAGES.lock().unwrap().push(message.to_string()),
+        debug_enabled: || DEBUG.load(SeqCst),
+        now: || NOW,
+        volume_id: || 0xCAFE_F00D,
+    }
+}
+
+fn new_disk() -> RamDisk {
+    RamDisk::new(400, 8, 32, 2, 100, 2, 1, 6, 0x12345678, *b"NO NAME    ")
+}
+
+// One test so the statics are not shared between threads
+#[test]
+fn hooks_are_used() {
+    let mut disk = new_disk();
+    let mut fs = FileSystem::new(&mut disk, hooks()).unwrap();
+    fs.init_fats().unwrap();
+    fs.create_root_dir().unwrap();
+
+    fs.create_file(2, "quiet.txt").unwrap();
+    assert!(MESSAGES.lock().unwrap().is_empty());
+
+    DEBUG.store(true, SeqCst);
+    fs.create_dir(2, "loud").unwrap();
+    DEBUG.store(false, SeqCst);
+    assert!(MESSAGES.lock().unwrap().iter().any(|message| message.contains("loud")));
+
+    let (_, entry) = fs.find_entry(2, "quiet.txt").unwrap();
+    assert_eq!(entry.creation_date, fat_date(&NOW));
+    assert_eq!(entry.write_time, fat_time(&NOW));
+
+    fs.print_tree(2, 0).unwrap();
+    assert!(LOG.lock().unwrap().contains("quiet.txt"));
+}
+
+#[test]
+fn default_hooks_stamp_the_fat_epoch() {
+    let mut disk = new_disk();
+    let mut fs = FileSystem::new(&mut disk, Hooks::default()).unwrap();
+    fs.init_fats().unwrap();
+    fs.create_root_dir().unwrap();
+
+    fs.create_file(2, "old.txt").unwrap();
+    let (_, entry) = fs.find_entry(2, "old.txt").unwrap();
+    assert_eq!(entry.creation_date, (1 << 5) | 1);
+    assert_eq!(entry.creation_time, 0);
+}
+
+#[test]
+fn mkfs_takes_the_serial_from_the_hooks() {
+    let mut disk = RamDisk::blank(70_000);
+    mkfs::format(&mut disk, 70_000, None, hooks()).unwrap();
+
+    let mut boot_sector = [0u8; 512];
+    disk.read_sector(0, &mut boot_sector).unwrap();
+    assert_eq!(boot_sector[67..71], 0xCAFE_F00Du32.to_le_bytes());
+}
@@ -0,0 +1,226 @@
+// The tests that check our writes with dosfstools are ignored by default. Where mkfs.fat and
+// fsck.fat are installed run them with `cargo test -- --ignored`; without the tools they fail.
+
+mod common;
+
+use common::{fsck_fat, mkfs_fat, Image, ImageFile};
+
+use fat32::error::FsError;
+use fat32::{mkfs, BlockDevice, FatType, FileSystem, Hooks, RamDisk};
+
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+// Leaves a bit of everything on the volume: long names, empty and multi-cluster files, nested
+// directories, and files that were shrunk, moved and removed on the way
+fn write_tree(fs: &mut FileSystem<'_, ImageFile>) {
+    let root = fs.root_cluster();
+
+    fs.create_file(root, "readme.txt").unwrap();
+    fs.write_file(root, "readme.txt", b"A volume written by the fat32 crate\n").unwrap();
+
+    fs.create_file(root, "A file with a long name.dat").unwrap();
+    fs.write_file(root, "A file with a long name.dat", &pattern(5000, 1)).unwrap();
+    fs.create_file(root, "empty").unwrap();
+
+    fs.create_dir(root, "docs").unwrap();
+    let docs = fs.resolve_dir(root, "/docs").unwrap();
+    fs.create_dir(docs, "deep").unwrap();
+    let deep = fs.resolve_dir(docs, "deep").unwrap();
+    fs.create_file(deep, "data.bin").unwrap();
+    fs.write_file(deep, "data.bin", &pattern(200_000, 2)).unwrap();
+    fs.append(deep, "data.bin", &pattern(1234, 3)).unwrap();
+
+    // Shrunk, then emptied
+    fs.create_file(docs, "shrinking.log").unwrap();
+    fs.write_file(docs, "shrinking.log", &pattern(20_000, 4)).unwrap();
+    fs.write_file(docs, "shrinking.log", &pattern(700, 5)).unwrap();
+    fs.create_file(docs, "emptied.log").unwrap();
+    fs.write_file(docs, "emptied.log", &pattern(3000, 6)).unwrap();
+    fs.write_file(docs, "emptied.log", b"").unwrap();
+
+    fs.create_dir(root, "scratch").unwrap();
+    let scratch = fs.resolve_dir(root, "scratch").unwrap();
+    for i in 0..20 {
+        let name = format!("temporary file {}.tmp", i);
+        fs.create_file(scratch, &name).unwrap();
+        fs.write_file(scratch, &name, &pattern(600 * i, i as u8)).unwrap();
+    }
+    fs.remove_dir(root, "scratch", true).unwrap();
+
+    fs.rename(root, "readme.txt", docs, "README.TXT").unwrap();
+    fs.rename(docs, "deep", root, "moved up").unwrap();
+}
+
+fn check_tree(fs: &mut FileSystem<'_, ImageFile>) {
+    let root = fs.root_cluster();
+
+    assert_eq!(fs.read_file(root, "A file with a long name.dat").unwrap(), pattern(5000, 1));
+    assert_eq!(fs.read_file(root, "empty").unwrap(), b"");
+    assert_eq!(fs.find_dir_in(root, "scratch"), Err(FsError::NotFound));
+
+    let docs = fs.resolve_dir(root, "docs").unwrap();
+    assert_eq!(fs.read_file(docs, "README.TXT").unwrap(), b"A volume written by the fat32 crate\n");
+    assert_eq!(fs.read_file(docs, "shrinking.log").unwrap(), pattern(700, 5));
+    assert_eq!(fs.read_file(docs, "emptied.log").unwrap(), b"");
+
+    let moved = fs.resolve_dir(root, "/moved up").unwrap();
+    assert_eq!(fs.resolve_dir(moved, "..").unwrap(), root);
+    let mut data = pattern(200_000, 2);
+    data.extend(pattern(1234, 3));
+    assert_eq!(fs.read_file(moved, "data.bin").unwrap(), data);
+
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+}
+
+// Writes to an image made by mkfs.fat, has fsck.fat look at the result and reads it back
+fn round_trip(name: &str, fat_bits: u8, size_kb: u64, fat_type: FatType) {
+    let image = mkfs_fat(name, fat_bits, size_kb, "MKFS");
+
+    {
+        let mut file = image.open();
+        let mut fs = FileSystem::new(&mut file, Hooks::default()).unwrap();
+        assert_eq!(fs.fat_type(), fat_type);
+        assert_eq!(&fs.ebr.volume_label, b"MKFS       ");
+        assert_eq!(fs.check(false).unwrap(), vec![]);
+
+        let free = fs.free_clusters().unwrap();
+        write_tree(&mut fs);
+        assert!(fs.free_clusters().unwrap() < free);
+        fs.sync().unwrap();
+    }
+
+    fsck_fat(&image);
+
+    let mut file = image.open();
+    let mut fs = FileSystem::new(&mut file, Hooks::default()).unwrap();
+    check_tree(&mut fs);
+}
+
+#[test]
+#[ignore = "needs mkfs.fat and fsck.fat from dosfstools"]
+fn fat12_floppy() {
+    round_trip("fat12", 12, 1440, FatType::Fat12);
+}
+
+#[test]
+#[ignore = "needs mkfs.fat and fsck.fat from dosfstools"]
+fn fat16_image() {
+    round_trip("fat16", 16, 16 * 1024, FatType::Fat16);
+}
+
+#[test]
+#[ignore = "needs mkfs.fat and fsck.fat from dosfstools"]
+fn fat32_image() {
+    round_trip("fat32", 32, 64 * 1024, FatType::Fat32);
+}
+
+#[test]
+#[ignore = "needs mkfs.fat and fsck.fat from dosfstools"]
+fn our_mkfs_passes_fsck() {
+    let image = Image::blank("mkfs", 70_000);
+
+    {
+        let mut file = image.open();
+        mkfs::format(&mut file, 70_000, Some("ups"), Hooks::default()).unwrap();
+    }
+    fsck_fat(&image);
+
+    {
+        let mut file = image.open();
+        let mut fs = FileSystem::new(&mut file, Hooks::default()).unwrap();
+        write_tree(&mut fs);
+    }
+    fsck_fat(&image);
+
+    let mut file = image.open();
+    let mut fs = FileSystem::new(&mut file, Hooks::default()).unwrap();
+    check_tree(&mut fs);
+}
+
+// 681 one-sector clusters give an odd number of FAT12 entries whose first 1024 bytes end on a
+// sector boundary, so the last entry is split between the second and the third FAT sector
+#[test]
+fn fat12_last_entry_across_sectors() {
+    let mut disk = RamDisk::blank(689);
+
+    let mut sector = [0u8; 512];
+    sector[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    sector[3..11].copy_from_slice(b"MSWIN4.1");
+    sector[11..13].copy_from_slice(&512u16.to_le_bytes());
+    sector[13] = 1;
+    sector[14..16].copy_from_slice(&1u16.to_le_bytes());
+    sector[16] = 2;
+    sector[17..19].copy_from_slice(&16u16.to_le_bytes());
+    sector[19..21].copy_from_slice(&689u16.to_le_bytes());
+    sector[21] = 0xF0;
+    sector[22..24].copy_from_slice(&3u16.to_le_bytes());
+    sector[38] = 0x29;
+    sector[43..54].copy_from_slice(b"NO NAME    ");
+    sector[54..62].copy_from_slice(b"FAT12   ");
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+    disk.write_sector(0, &sector).unwrap();
+
+    // Cluster 682 links to 512, whose entry is the end of the chain
+    for fat_lba in [1, 4] {
+        let mut fat = [0u8; 3 * 512];
+        fat[0..3].copy_from_slice(&[0xF0, 0xFF, 0xFF]);
+        fat[768..770].copy_from_slice(&[0xFF, 0x0F]);
+        fat[1023..1025].copy_from_slice(&[0x00, 0x02]);
+        for (i, chunk) in fat.chunks_exact(512).enumerate() {
+            disk.write_sector(fat_lba + i as u64, chunk.try_into().unwrap()).unwrap();
+        }
+    }
+
+    let mut sector = [0u8; 512];
+    sector[0..11].copy_from_slice(b"LAST    BIN");
+    sector[11] = 0x20;
+    sector[26..28].copy_from_slice(&682u16.to_le_bytes());
+    sector[28..32].copy_from_slice(&1024u32.to_le_bytes());
+    disk.write_sector(7, &sector).unwrap();
+
+    let mut fs = FileSystem::new(&mut disk, Hooks::default()).unwrap();
+    assert_eq!(fs.fat_type(), FatType::Fat12);
+    assert_eq!(fs.count_occupied_clusters().unwrap(), 4);
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+}
+
+// Boot sectors whose layout doesn't add up are rejected, not mounted with wrapped offsets
+#[test]
+fn crafted_boot_sectors_are_corrupt() {
+    let mut disk = RamDisk::blank(70_000);
+    mkfs::format(&mut disk, 70_000, None, Hooks::default()).unwrap();
+
+    let mut good = [0u8; 512];
+    disk.read_sector(0, &mut good).unwrap();
+
+    let patched = |patches: &[(usize, &[u8])]| {
+        let mut sector = good;
+        for &(offset, bytes) in patches {
+            sector[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        sector
+    };
+
+    let cases = [
+        ("FATs past 4G sectors", patched(&[(36, &0x8000_0000u32.to_le_bytes())])),
+        ("fixed root past 4G sectors", patched(&[
+            (14, &0xFFFFu16.to_le_bytes()),
+            (17, &512u16.to_le_bytes()),
+            (36, &0x7FFF_8000u32.to_le_bytes()),
+        ])),
+        ("root cluster 0", patched(&[(44, &0u32.to_le_bytes())])),
+        ("root cluster 1", patched(&[(44, &1u32.to_le_bytes())])),
+    ];
+
+    for (name, sector) in cases {
+        disk.write_sector(0, &sector).unwrap();
+        let result = FileSystem::new(&mut disk, Hooks::default());
+        assert_eq!(result.err(), Some(FsError::Corrupt), "{}", name);
+    }
+
+    disk.write_sector(0, &good).unwrap();
+    assert!(FileSystem::new(&mut disk, Hooks::default()).is_ok());
+}
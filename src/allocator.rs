@@ -8,7 +8,9 @@ use linked_list_allocator::LockedHeap;
 pub struct Dummy;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 10 * 1024 * 1024;
+// The RAM disk alone is 34 MiB, the smallest disk mkfs can give the 65525
+// clusters FAT32 needs. The README lists the memory this takes from QEMU.
+pub const HEAP_SIZE: usize = 48 * 1024 * 1024;
 
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
@@ -0,0 +1,281 @@
+use alloc::string::String;
+
+use x86_64::instructions::port::Port;
+
+use crate::fs::error::FsError;
+use crate::fs::fat32::BlockDevice;
+
+// I/O ports of the primary channel
+const PRIMARY_IO: u16 = 0x1F0;
+const PRIMARY_CONTROL: u16 = 0x3F6;
+
+// Offsets from the I/O base
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+const CMD_CACHE_FLUSH_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+// Device control: no interrupts, everything is polled
+const CONTROL_NIEN: u8 = 0x02;
+
+// Polls of the status register before a command is considered failed
+const TIMEOUT: usize = 1_000_000;
+
+// Largest sector count LBA28 can address
+const LBA28_LIMIT: u64 = 1 << 28;
+
+// A drive on the primary ATA channel, driven with PIO and polling
+pub struct AtaDrive {
+    io_base: u16,
+    control_base: u16,
+    slave: bool,
+    sectors: u64,
+    lba48: bool,
+    model: String,
+}
+
+impl AtaDrive {
+    pub fn primary_master() -> Option<Self> {
+        Self::identify(PRIMARY_IO, PRIMARY_CONTROL, false)
+    }
+
+    pub fn primary_slave() -> Option<Self> {
+        Self::identify(PRIMARY_IO, PRIMARY_CONTROL, true)
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn supports_lba48(&self) -> bool {
+        self.lba48
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn read_reg(&self, reg: u16) -> u8 {
+        let mut port: Port<u8> = Port::new(self.io_base + reg);
+        unsafe { port.read() }
+    }
+
+    fn write_reg(&self, reg: u16, value: u8) {
+        let mut port: Port<u8> = Port::new(self.io_base + reg);
+        unsafe { port.write(value) }
+    }
+
+    // Reading the alternate status register doesn't acknowledge anything, four reads give the
+    // drive the 400ns it needs to update the status after a command or drive select
+    fn delay_400ns(&self) {
+        let mut alt_status: Port<u8> = Port::new(self.control_base);
+        for _ in 0..4 {
+            unsafe { alt_status.read() };
+        }
+    }
+
+    // A floating bus reads as 0xFF, with BSY set, so polling it would only run out the timeout
+    fn read_status(&self) -> Result<u8, FsError> {
+        match self.read_reg(REG_STATUS) {
+            0xFF => Err(FsError::Io),
+            status => Ok(status),
+        }
+    }
+
+    fn wait_not_busy(&self) -> Result<u8, FsError> {
+        for _ in 0..TIMEOUT {
+            let status = self.read_status()?;
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(FsError::Io)
+    }
+
+    // Waits until the drive is ready to transfer a sector
+    fn wait_data_request(&self) -> Result<(), FsError> {
+        for _ in 0..TIMEOUT {
+            let status = self.read_status()?;
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(FsError::Io);
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(FsError::Io)
+    }
+
+    fn select(&self, lba_bits: u8) {
+        let drive = if self.slave { 0xF0 } else { 0xE0 };
+        self.write_reg(REG_DRIVE, drive | (lba_bits & 0x0F));
+        self.delay_400ns();
+    }
+
+    fn identify(io_base: u16, control_base: u16, slave: bool) -> Option<Self> {
+        let mut drive = Self {
+            io_base,
+            control_base,
+            slave,
+            sectors: 0,
+            lba48: false,
+            model: String::new(),
+        };
+
+        let mut control: Port<u8> = Port::new(control_base);
+        unsafe { control.write(CONTROL_NIEN) };
+
+        // A floating bus reads as 0xFF, there is no controller at all
+        if drive.read_reg(REG_STATUS) == 0xFF {
+            return None;
+        }
+
+        drive.write_reg(REG_DRIVE, if slave { 0xB0 } else { 0xA0 });
+        drive.delay_400ns();
+
+        drive.write_reg(REG_SECTOR_COUNT, 0);
+        drive.write_reg(REG_LBA_LOW, 0);
+        drive.write_reg(REG_LBA_MID, 0);
+        drive.write_reg(REG_LBA_HIGH, 0);
+        drive.write_reg(REG_COMMAND, CMD_IDENTIFY);
+        drive.delay_400ns();
+
+        // No drive answers with a status of 0
+        if drive.read_reg(REG_STATUS) == 0 {
+            return None;
+        }
+        drive.wait_not_busy().ok()?;
+
+        // ATAPI and SATA devices set these to their signature instead of answering IDENTIFY
+        if drive.read_reg(REG_LBA_MID) != 0 || drive.read_reg(REG_LBA_HIGH) != 0 {
+            return None;
+        }
+        drive.wait_data_request().ok()?;
+
+        let mut data: Port<u16> = Port::new(io_base + REG_DATA);
+        let mut identify = [0u16; 256];
+        for word in identify.iter_mut() {
+            *word = unsafe { data.read() };
+        }
+
+        // Word 83 bit 10: the 48-bit feature set is supported
+        drive.lba48 = identify[83] & (1 << 10) != 0;
+
+        let lba28_sectors = identify[60] as u64 | (identify[61] as u64) << 16;
+        let lba48_sectors = identify[100..104].iter()
+            .rev()
+            .fold(0u64, |acc, &word| (acc << 16) | word as u64);
+
+        drive.sectors = if drive.lba48 && lba48_sectors != 0 { lba48_sectors } else { lba28_sectors };
+        if drive.sectors == 0 {
+            return None;
+        }
+
+        // Words 27-46 hold the model name, two characters per word with the first in the high byte
+        let mut model = String::new();
+        for &word in identify[27..47].iter() {
+            model.push((word >> 8) as u8 as char);
+            model.push((word & 0xFF) as u8 as char);
+        }
+        drive.model = String::from(model.trim());
+
+        Some(drive)
+    }
+
+    // Sets up the registers and sends a one sector read or write command
+    fn start_transfer(&self, lba: u64, lba28_cmd: u8, lba48_cmd: u8) -> Result<(), FsError> {
+        if lba >= self.sectors {
+            return Err(FsError::Io);
+        }
+
+        self.wait_not_busy()?;
+
+        if lba < LBA28_LIMIT {
+            self.select((lba >> 24) as u8);
+            self.write_reg(REG_SECTOR_COUNT, 1);
+            self.write_reg(REG_LBA_LOW, lba as u8);
+            self.write_reg(REG_LBA_MID, (lba >> 8) as u8);
+            self.write_reg(REG_LBA_HIGH, (lba >> 16) as u8);
+            self.write_reg(REG_COMMAND, lba28_cmd);
+        } else if self.lba48 {
+            self.select(0);
+            // The high bytes go first, each register holds two values
+            self.write_reg(REG_SECTOR_COUNT, 0);
+            self.write_reg(REG_LBA_LOW, (lba >> 24) as u8);
+            self.write_reg(REG_LBA_MID, (lba >> 32) as u8);
+            self.write_reg(REG_LBA_HIGH, (lba >> 40) as u8);
+            self.write_reg(REG_SECTOR_COUNT, 1);
+            self.write_reg(REG_LBA_LOW, lba as u8);
+            self.write_reg(REG_LBA_MID, (lba >> 8) as u8);
+            self.write_reg(REG_LBA_HIGH, (lba >> 16) as u8);
+            self.write_reg(REG_COMMAND, lba48_cmd);
+        } else {
+            return Err(FsError::Io);
+        }
+
+        self.delay_400ns();
+        self.wait_data_request()
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        self.start_transfer(lba, CMD_READ_SECTORS, CMD_READ_SECTORS_EXT)?;
+
+        let mut data: Port<u16> = Port::new(self.io_base + REG_DATA);
+        for chunk in buf.chunks_exact_mut(2) {
+            let word = unsafe { data.read() };
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        self.start_transfer(lba, CMD_WRITE_SECTORS, CMD_WRITE_SECTORS_EXT)?;
+
+        let mut data: Port<u16> = Port::new(self.io_base + REG_DATA);
+        for chunk in buf.chunks_exact(2) {
+            unsafe { data.write(u16::from_le_bytes([chunk[0], chunk[1]])) };
+        }
+
+        let status = self.wait_not_busy()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err(FsError::Io);
+        }
+        Ok(())
+    }
+
+    // Drives have their own write cache, it is flushed so the data survives a power off
+    fn sync(&mut self) -> Result<(), FsError> {
+        self.wait_not_busy()?;
+        self.select(0);
+        self.write_reg(REG_COMMAND, if self.lba48 { CMD_CACHE_FLUSH_EXT } else { CMD_CACHE_FLUSH });
+        self.delay_400ns();
+
+        let status = self.wait_not_busy()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err(FsError::Io);
+        }
+        Ok(())
+    }
+}
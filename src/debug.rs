@@ -5,11 +5,15 @@ use crate::interrupts;
 use crate::AtomicBool;
 use crate::vga_buffer::WRITER;
 use crate::{print, println};
+use crate::shell::{self, Command, Context, UsageError};
+
+use core::fmt::Write;
 
 pub static DEBUG_FS: AtomicBool = AtomicBool::new(false);
 
 
 use crate::DEBUG_MODE;
+use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering::SeqCst;
 
 const DEBUG_BUF_SIZE: usize = 1024;
@@ -18,6 +22,8 @@ const VGA_BUFFER_ADDR: usize = 0xb8000;
 const BUFFER_SIZE: usize = 80 * 25 * 2;
 
 static SAVED_SCREEN: Mutex<[u8; BUFFER_SIZE]> = Mutex::new([0; BUFFER_SIZE]);
+// Column the writer was at when the screen was saved
+static SAVED_COLUMN: AtomicUsize = AtomicUsize::new(0);
 
 static DEBUG_BUFFER: Mutex<DebugBuffer> = Mutex::new(DebugBuffer::new());
 
@@ -74,6 +80,7 @@ pub fn trigger_debug() {
             BUFFER_SIZE,
         );
     }
+    SAVED_COLUMN.store(WRITER.lock().column(), SeqCst);
 
     interrupts::unmask_irq0();
     DEBUG_MODE.store(true, SeqCst);
@@ -107,8 +114,45 @@ pub fn restore_screen_buffer() {
     }
 
     let mut writer = WRITER.lock();
-    writer.cursor_position(2);
+    writer.cursor_position(SAVED_COLUMN.load(SeqCst));
 
     interrupts::mask_irq0();
 }
 
+struct Bk;
+
+impl Command for Bk {
+    fn name(&self) -> &'static str { "bk" }
+    fn usage(&self) -> &'static str { "bk <component>" }
+    fn help(&self) -> &'static str { "Toggle FileSystem debugging on or off." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        match args.first() {
+            Some(&"fs") => {
+                let val = DEBUG_FS.load(SeqCst);
+                DEBUG_FS.store(!val, SeqCst);
+
+                if DEBUG_FS.load(SeqCst) {
+                    let _ = writeln!(ctx.out, "FileSystem debugging activated");
+                }
+                else{
+                    let _ = writeln!(ctx.out, "FileSystem debugging deactivated");
+                }
+            }
+            Some(&"help") => {
+
+                let _ = writeln!(ctx.out, "\
+Available components:
+
+fs
+File system.");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub fn register_commands() {
+    shell::register(&Bk);
+}
@@ -0,0 +1,156 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::fs::error::FsError;
+use crate::fs::fat32::BlockDevice;
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    // Dirty sectors written to the device, on eviction or sync
+    pub writebacks: u64,
+}
+
+struct CachedSector {
+    lba: u64,
+    data: [u8; SECTOR_SIZE],
+    dirty: bool,
+    last_used: u64,
+}
+
+// Write-back LRU cache of whole sectors in front of another device. Writes only reach
+// the device when a dirty sector is evicted or on `sync`.
+pub struct CachedDevice<D: BlockDevice> {
+    device: D,
+    capacity: usize,
+    sectors: Vec<CachedSector>,
+    // lba -> index in `sectors`
+    index: BTreeMap<u64, usize>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<D: BlockDevice> CachedDevice<D> {
+    pub fn new(device: D, capacity: usize) -> Self {
+        Self {
+            device,
+            capacity: capacity.max(1),
+            sectors: Vec::with_capacity(capacity),
+            index: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn dirty_sectors(&self) -> usize {
+        self.sectors.iter().filter(|sector| sector.dirty).count()
+    }
+
+    // The device below the cache, which may be behind on writes until `sync` is called
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    fn touch(&mut self, idx: usize) {
+        self.clock += 1;
+        self.sectors[idx].last_used = self.clock;
+    }
+
+    // Index of a free slot for `lba`, evicting the least recently used sector if the cache is full
+    fn slot_for(&mut self, lba: u64) -> Result<usize, FsError> {
+        let idx = if self.sectors.len() < self.capacity {
+            self.sectors.push(CachedSector {
+                lba,
+                data: [0; SECTOR_SIZE],
+                dirty: false,
+                last_used: 0,
+            });
+            self.sectors.len() - 1
+        } else {
+            let idx = self.sectors.iter()
+                .enumerate()
+                .min_by_key(|(_, sector)| sector.last_used)
+                .map(|(idx, _)| idx)
+                .unwrap();
+
+            let victim = &mut self.sectors[idx];
+            if victim.dirty {
+                self.device.write_sector(victim.lba, &victim.data)?;
+                victim.dirty = false;
+                self.stats.writebacks += 1;
+            }
+            self.index.remove(&victim.lba);
+            victim.lba = lba;
+            idx
+        };
+
+        self.index.insert(lba, idx);
+        Ok(idx)
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for CachedDevice<D> {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        let idx = match self.index.get(&lba) {
+            Some(&idx) => {
+                self.stats.hits += 1;
+                idx
+            }
+            None => {
+                self.stats.misses += 1;
+
+                // Read before taking a slot so a failed read doesn't leave a bogus sector cached
+                let mut data = [0u8; SECTOR_SIZE];
+                self.device.read_sector(lba, &mut data)?;
+
+                let idx = self.slot_for(lba)?;
+                self.sectors[idx].data = data;
+                idx
+            }
+        };
+
+        self.touch(idx);
+        buf.copy_from_slice(&self.sectors[idx].data);
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        // The whole sector is replaced, so a miss doesn't need to read it first
+        let idx = match self.index.get(&lba) {
+            Some(&idx) => {
+                self.stats.hits += 1;
+                idx
+            }
+            None => {
+                self.stats.misses += 1;
+                self.slot_for(lba)?
+            }
+        };
+
+        self.touch(idx);
+        let sector = &mut self.sectors[idx];
+        sector.data.copy_from_slice(buf);
+        sector.dirty = true;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        for sector in self.sectors.iter_mut().filter(|sector| sector.dirty) {
+            self.device.write_sector(sector.lba, &sector.data)?;
+            sector.dirty = false;
+            self.stats.writebacks += 1;
+        }
+        self.device.sync()
+    }
+}
@@ -0,0 +1,799 @@
+use core::fmt::Write;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::disk::{Disk, RawDisk, SharedDisk, BLOCK_DEVICE};
+use crate::fs::error::FsError;
+use crate::fs::fat32::{BlockDevice, FileSystem};
+use crate::fs::file::OpenFlags;
+use crate::fs::partition::{read_partition_table, Partition};
+use crate::fs::vfs::{self, NodeKind, Vfs, VFS};
+use crate::fs::volume::FatVolume;
+use crate::fs::{mkfs, HOOKS};
+use crate::shell::{self, Command, Context, UsageError};
+
+// The shell commands for files and volumes
+pub fn register_commands() {
+    for command in [
+        &Ls as &'static dyn Command, &Mkdir, &Touch, &Cat, &WriteFile, &Echo, &Hexdump, &Rm, &Mv,
+        &Rmdir, &Cd, &Pwd, &Df, &Mkfs, &Lsblk, &Mount, &Umount, &Fsck, &Sync,
+    ] {
+        shell::register(command);
+    }
+}
+
+struct Ls;
+
+impl Command for Ls {
+    fn name(&self) -> &'static str { "ls" }
+    fn usage(&self) -> &'static str { "ls [path]" }
+    fn help(&self) -> &'static str {
+        "List the contents of the current directory, or of the directory at [path]."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let path = ctx.path(args.first().copied().unwrap_or(""));
+        let mut vfs = VFS.lock();
+        let result = vfs.stat(&path).and_then(|dir| {
+            let mut tree = String::new();
+            list_tree(&mut vfs, &path, 0, &mut vec![dir.id], &mut tree)?;
+            Ok(tree)
+        });
+
+        match result {
+            Ok(tree) => ctx.out.push_str(&tree),
+            Err(err) => {
+                let _ = writeln!(ctx.out, "ls: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Mkdir;
+
+impl Command for Mkdir {
+    fn name(&self) -> &'static str { "mkdir" }
+    fn usage(&self) -> &'static str { "mkdir <path>" }
+    fn help(&self) -> &'static str { "Create a new directory at <path>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        if let Err(err) = VFS.lock().create(&path, NodeKind::Directory) {
+            let _ = writeln!(ctx.out, "mkdir: cannot create directory '{}': {}", arg, err);
+        }
+        Ok(())
+    }
+}
+
+struct Touch;
+
+impl Command for Touch {
+    fn name(&self) -> &'static str { "touch" }
+    fn usage(&self) -> &'static str { "touch <path>" }
+    fn help(&self) -> &'static str { "Create a new empty file at <path>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        if let Err(err) = VFS.lock().create(&path, NodeKind::File) {
+            let _ = writeln!(ctx.out, "touch: cannot create file '{}': {}", arg, err);
+        }
+        Ok(())
+    }
+}
+
+struct Cat;
+
+impl Command for Cat {
+    fn name(&self) -> &'static str { "cat" }
+    fn usage(&self) -> &'static str { "cat <path>" }
+    fn help(&self) -> &'static str { "Print the contents of the file <path> as text." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        match read_file(&mut VFS.lock(), &path) {
+            Ok(data) => {
+                ctx.out.push_str(&String::from_utf8_lossy(&data));
+                if !data.is_empty() && !data.ends_with(b"\n") {
+                    let _ = writeln!(ctx.out, "");
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "cat: {}: {}", arg, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct WriteFile;
+
+impl Command for WriteFile {
+    fn name(&self) -> &'static str { "write" }
+    fn usage(&self) -> &'static str { "write <path> <text...>" }
+    fn help(&self) -> &'static str {
+        "Replace the contents of the file <path> with <text> and a new line, creating it if needed."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let (arg, text) = match args {
+            [arg, text @ ..] if !text.is_empty() => (arg, text),
+            _ => return Err(UsageError),
+        };
+        let path = ctx.path(arg);
+        let data = format!("{}\n", text.join(" "));
+
+        if let Err(err) = write_file(&mut VFS.lock(), &path, data.as_bytes(), false) {
+            let _ = writeln!(ctx.out, "write: {}: {}", arg, err);
+        }
+        Ok(())
+    }
+}
+
+struct Echo;
+
+impl Command for Echo {
+    fn name(&self) -> &'static str { "echo" }
+    fn usage(&self) -> &'static str { "echo <text...> [>> <path>]" }
+    fn help(&self) -> &'static str {
+        "Print <text>, or add it with a new line to the end of the file <path> with >>, or replace the file with >."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        // A redirection is the last two words, like `echo hi >> notes.txt`
+        match args {
+            [words @ .., op, target] if *op == ">>" || *op == ">" => {
+                let path = ctx.path(target);
+                let data = format!("{}\n", words.join(" "));
+
+                if let Err(err) = write_file(&mut VFS.lock(), &path, data.as_bytes(), *op == ">>") {
+                    let _ = writeln!(ctx.out, "echo: {}: {}", target, err);
+                }
+                Ok(())
+            }
+            _ if args.iter().any(|word| *word == ">>" || *word == ">") => Err(UsageError),
+            _ => {
+                let _ = writeln!(ctx.out, "{}", args.join(" "));
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Hexdump;
+
+impl Command for Hexdump {
+    fn name(&self) -> &'static str { "hexdump" }
+    fn usage(&self) -> &'static str { "hexdump <path>" }
+    fn help(&self) -> &'static str {
+        "Print the contents of the file <path> in hexadecimal, 16 bytes per line."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        match read_file(&mut VFS.lock(), &path) {
+            Ok(data) => hexdump(&data, ctx.out),
+            Err(err) => {
+                let _ = writeln!(ctx.out, "hexdump: {}: {}", arg, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Rm;
+
+impl Command for Rm {
+    fn name(&self) -> &'static str { "rm" }
+    fn usage(&self) -> &'static str { "rm [-r] <path>" }
+    fn help(&self) -> &'static str {
+        "Remove the file <path>. With -r directories are removed too, with everything inside them."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let (recursive, name) = match args {
+            ["-r", name, ..] => (true, name),
+            [name, ..] if *name != "-r" => (false, name),
+            _ => return Err(UsageError),
+        };
+        let path = ctx.path(name);
+
+        let mut vfs = VFS.lock();
+        let result = vfs.stat(&path).and_then(|node| {
+            if node.is_dir() && !recursive {
+                return Err(FsError::IsADirectory);
+            }
+            vfs.remove(&path, recursive)
+        });
+
+        if let Err(err) = result {
+            let _ = writeln!(ctx.out, "rm: cannot remove '{}': {}", name, err);
+        }
+        Ok(())
+    }
+}
+
+struct Mv;
+
+impl Command for Mv {
+    fn name(&self) -> &'static str { "mv" }
+    fn usage(&self) -> &'static str { "mv <source> <destination>" }
+    fn help(&self) -> &'static str {
+        "Rename <source> to <destination>, or move it inside <destination> if that is a directory."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let (arg, target) = match args {
+            [arg, target, ..] => (arg, target),
+            _ => return Err(UsageError),
+        };
+        let src = ctx.path(arg);
+        let dst = ctx.path(target);
+
+        let mut vfs = VFS.lock();
+        let result = vfs.stat(&src).and_then(|src_node| {
+            let (_, src_name) = vfs::split_parent(&src)?;
+
+            // Moving onto a directory puts the entry inside it under the same name,
+            // unless that directory is the source itself and only its case changes
+            match vfs.stat(&dst) {
+                Ok(dir) if dir.is_dir() && dir != src_node => {
+                    vfs.rename(&src, &format!("{}/{}", dst.trim_end_matches('/'), src_name))
+                }
+                _ => vfs.rename(&src, &dst),
+            }
+        });
+
+        if let Err(err) = result {
+            let _ = writeln!(ctx.out, "mv: cannot move '{}' to '{}': {}", arg, target, err);
+        }
+        Ok(())
+    }
+}
+
+struct Rmdir;
+
+impl Command for Rmdir {
+    fn name(&self) -> &'static str { "rmdir" }
+    fn usage(&self) -> &'static str { "rmdir <path>" }
+    fn help(&self) -> &'static str { "Remove the empty directory at <path>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        let mut vfs = VFS.lock();
+        let result = vfs.stat(&path).and_then(|node| {
+            if !node.is_dir() {
+                return Err(FsError::NotADirectory);
+            }
+            vfs.remove(&path, false)
+        });
+
+        if let Err(err) = result {
+            let _ = writeln!(ctx.out, "rmdir: failed to remove '{}': {}", arg, err);
+        }
+        Ok(())
+    }
+}
+
+struct Cd;
+
+impl Command for Cd {
+    fn name(&self) -> &'static str { "cd" }
+    fn usage(&self) -> &'static str { "cd <path>" }
+    fn help(&self) -> &'static str { "Change the current directory to <path>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let arg = args.first().ok_or(UsageError)?;
+        let path = ctx.path(arg);
+
+        let mut vfs = VFS.lock();
+        let result = vfs.stat(&path).and_then(|node| {
+            if node.is_dir() { canonical_path(&mut vfs, &path) } else { Err(FsError::NotADirectory) }
+        });
+
+        match result {
+            Ok(path) => {
+                *ctx.cwd = path;
+                let _ = writeln!(ctx.out, "Changed directory to {}", arg);
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "cd: {}: {}", arg, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Pwd;
+
+impl Command for Pwd {
+    fn name(&self) -> &'static str { "pwd" }
+    fn usage(&self) -> &'static str { "pwd" }
+    fn help(&self) -> &'static str {
+        "Show the path of the current directory, which the prompt shows too."
+    }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let _ = writeln!(ctx.out, "{}", ctx.cwd);
+        Ok(())
+    }
+}
+
+struct Df;
+
+impl Command for Df {
+    fn name(&self) -> &'static str { "df" }
+    fn usage(&self) -> &'static str { "df" }
+    fn help(&self) -> &'static str {
+        "Show the size, used and free space in bytes of every mounted file system."
+    }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let mut vfs = VFS.lock();
+        let _ = writeln!(ctx.out, "{:>10} {:>10} {:>10}  {}", "Size", "Used", "Free", "Mounted on");
+
+        for (path, _) in vfs.mounts() {
+            match vfs.stats(&path) {
+                Ok(stats) => {
+                    let total = stats.total_blocks * stats.block_size;
+                    let free = stats.free_blocks * stats.block_size;
+                    let _ = writeln!(ctx.out, "{:>10} {:>10} {:>10}  {}", total, total - free, free, path);
+                }
+                Err(err) => {
+                    let _ = writeln!(ctx.out, "df: {}: {}", path, err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Mkfs;
+
+impl Command for Mkfs {
+    fn name(&self) -> &'static str { "mkfs" }
+    fn usage(&self) -> &'static str { "mkfs [label]" }
+    fn help(&self) -> &'static str {
+        "Format the disk, or the partition in use, as an empty FAT32 file system. Everything on it is lost."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let mut vfs = VFS.lock();
+        let label = args.first().copied();
+
+        if let Some(reason) = root_in_use(&vfs) {
+            let _ = writeln!(ctx.out, "mkfs: {}, {}", FsError::Busy, reason);
+            return Ok(());
+        }
+
+        // The volume on the disk keeps its boot sector, FSInfo and type, which formatting changes
+        let was_mounted = vfs.is_mount_point("/");
+        if was_mounted {
+            if let Err(err) = vfs.unmount("/") {
+                let _ = writeln!(ctx.out, "mkfs: cannot unmount /: {}", err);
+                return Ok(());
+            }
+        }
+        *ctx.cwd = String::from("/");
+
+        let mut dev = BLOCK_DEVICE.lock();
+        let sectors = dev.inner().sector_count();
+        let result = mkfs::format(&mut *dev, sectors, label, HOOKS);
+        drop(dev);
+
+        match result {
+            Ok(()) => {
+                let _ = writeln!(ctx.out, "Formatted {} sectors as FAT32", sectors);
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "mkfs: {}", err);
+            }
+        }
+
+        // Mount what is on the disk now, the old file system if formatting failed before touching it
+        if result.is_ok() || was_mounted {
+            if let Err(err) = mount_root(&mut vfs) {
+                let _ = writeln!(ctx.out, "mkfs: cannot mount the file system: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Lsblk;
+
+impl Command for Lsblk {
+    fn name(&self) -> &'static str { "lsblk" }
+    fn usage(&self) -> &'static str { "lsblk" }
+    fn help(&self) -> &'static str {
+        "List the disk and its partitions. The one the file system is on is marked with *."
+    }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let (mounted, sectors, name) = {
+            let dev = BLOCK_DEVICE.lock();
+            let disk = dev.inner().inner();
+            let name = match disk {
+                Disk::Ata(drive) => String::from(drive.model()),
+                Disk::Ram(_) => String::from("RAM disk"),
+            };
+            (dev.inner().start_lba(), disk.sector_count(), name)
+        };
+
+        // Through the cache, which may hold a newer table than the disk
+        match read_partition_table(&mut RawDisk, sectors) {
+            Ok(table) => {
+                let _ = writeln!(ctx.out, "{:<6} {:>10} {:>10} {:>7}  {}", "NAME", "START", "SECTORS", "SIZE", "TYPE");
+
+                let scheme = match &table {
+                    Some(table) => format!("{}", table.scheme),
+                    None => String::from("no partition table"),
+                };
+                let mark = if mounted == 0 { "*" } else { "" };
+                let _ = writeln!(ctx.out, "{:<6} {:>10} {:>10} {:>5}MB  {}, {} {}", "disk", 0, sectors, sectors / 2048, name, scheme, mark);
+
+                for partition in table.iter().flat_map(|table| table.partitions.iter()) {
+                    let mark = if partition.start_lba == mounted { "*" } else { "" };
+                    let _ = writeln!(
+                        ctx.out,
+                        "{:<6} {:>10} {:>10} {:>5}MB  {} {} {}",
+                        format!("part{}", partition.number),
+                        partition.start_lba,
+                        partition.sector_count,
+                        partition.sector_count / 2048,
+                        partition.kind,
+                        partition.name,
+                        mark,
+                    );
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "lsblk: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Mount;
+
+impl Command for Mount {
+    fn name(&self) -> &'static str { "mount" }
+    fn usage(&self) -> &'static str { "mount [<partition> <path>]" }
+    fn help(&self) -> &'static str {
+        "List the mounted file systems, or mount a FAT partition like part2 on the directory <path>."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let mut vfs = VFS.lock();
+
+        match args {
+            [] => {
+                for (path, fs_type) in vfs.mounts() {
+                    let _ = writeln!(ctx.out, "{} on {}", fs_type, path);
+                }
+            }
+            [partition, target, ..] => {
+                let path = ctx.path(target);
+                if let Err(err) = mount_partition(&mut vfs, partition, &path) {
+                    let _ = writeln!(ctx.out, "mount: cannot mount {} on {}: {}", partition, path, err);
+                }
+            }
+            _ => return Err(UsageError),
+        }
+        Ok(())
+    }
+}
+
+struct Umount;
+
+impl Command for Umount {
+    fn name(&self) -> &'static str { "umount" }
+    fn usage(&self) -> &'static str { "umount <path>" }
+    fn help(&self) -> &'static str { "Unmount the file system mounted on <path>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let path = ctx.path(args.first().ok_or(UsageError)?);
+        let result = if path == "/" {
+            Err(FsError::Busy)
+        } else {
+            VFS.lock().unmount(&path).map(|_| ())
+        };
+
+        match result {
+            Ok(()) => {
+                // The current directory may have been on it
+                if *ctx.cwd == path || ctx.cwd.starts_with(&format!("{}/", path)) {
+                    *ctx.cwd = String::from("/");
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "umount: {}: {}", path, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Fsck;
+
+impl Command for Fsck {
+    fn name(&self) -> &'static str { "fsck" }
+    fn usage(&self) -> &'static str { "fsck [-r]" }
+    fn help(&self) -> &'static str {
+        "Check the file system for errors. With -r the errors are also fixed, once no file is open and no other volume is mounted."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let repair = match args {
+            [] => false,
+            ["-r"] => true,
+            _ => return Err(UsageError),
+        };
+
+        // Repairs free and move clusters that open files and the mounted volume point to, so they
+        // are done with / unmounted
+        let mut vfs = VFS.lock();
+        let was_mounted = repair && vfs.is_mount_point("/");
+        if repair {
+            if let Some(reason) = root_in_use(&vfs) {
+                let _ = writeln!(ctx.out, "fsck: {}, {}", FsError::Busy, reason);
+                return Ok(());
+            }
+            if was_mounted {
+                if let Err(err) = vfs.unmount("/") {
+                    let _ = writeln!(ctx.out, "fsck: cannot unmount /: {}", err);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut dev = BLOCK_DEVICE.lock();
+        let result = FileSystem::new(&mut *dev, HOOKS)
+            .and_then(|mut fs| fs.check(repair));
+        drop(dev);
+
+        match result {
+            Ok(problems) if problems.is_empty() => {
+                let _ = writeln!(ctx.out, "No problems found");
+            }
+            Ok(problems) => {
+                for problem in problems.iter() {
+                    let _ = writeln!(ctx.out, "{}", problem);
+                }
+                if repair {
+                    let _ = writeln!(ctx.out, "{} problems found and fixed", problems.len());
+                } else {
+                    let _ = writeln!(ctx.out, "{} problems found, run fsck -r to fix them", problems.len());
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "fsck: {}", err);
+            }
+        }
+
+        if was_mounted {
+            if let Err(err) = mount_root(&mut vfs) {
+                let _ = writeln!(ctx.out, "fsck: cannot mount the file system: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Sync;
+
+impl Command for Sync {
+    fn name(&self) -> &'static str { "sync" }
+    fn usage(&self) -> &'static str { "sync" }
+    fn help(&self) -> &'static str {
+        "Write every cached change to the disk and show cache statistics."
+    }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let dirty = BLOCK_DEVICE.lock().dirty_sectors();
+
+        // The root volume syncs the cache too, unless nothing is mounted
+        let result = VFS.lock().sync().and_then(|()| BLOCK_DEVICE.lock().sync());
+
+        match result {
+            Ok(()) => {
+                let stats = BLOCK_DEVICE.lock().stats();
+                let _ = writeln!(ctx.out, "Wrote {} sectors to disk", dirty);
+                let _ = writeln!(ctx.out, "Cache hits: {}, misses: {}, write-backs: {}", stats.hits, stats.misses, stats.writebacks);
+            }
+            Err(err) => {
+                let _ = writeln!(ctx.out, "sync: {}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Everything below `path` as a tree, laid out like `FileSystem::return_tree`. `ancestors` are
+// the directories above on the same file system, one that loops back to them isn't followed.
+fn list_tree(vfs: &mut Vfs, path: &str, depth: usize, ancestors: &mut Vec<u64>, out: &mut String) -> Result<(), FsError> {
+    for entry in vfs.read_dir(path)? {
+        for _ in 0..depth {
+            out.push_str("\\ ");
+        }
+
+        let child = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+
+        if !entry.node.is_dir() {
+            let _ = writeln!(out, "* {}", entry.name);
+        } else if vfs.is_mount_point(&child) {
+            // Another file system starts here, its ids have nothing to do with these
+            let _ = writeln!(out, "* {}", entry.name);
+            let root = vfs.stat(&child)?;
+            list_tree(vfs, &child, depth + 1, &mut vec![root.id], out)?;
+        } else if ancestors.contains(&entry.node.id) {
+            let _ = writeln!(out, "* {} (loops back to a parent directory)", entry.name);
+        } else {
+            let _ = writeln!(out, "* {}", entry.name);
+
+            ancestors.push(entry.node.id);
+            let result = list_tree(vfs, &child, depth + 1, ancestors, out);
+            ancestors.pop();
+            result?;
+        }
+    }
+    Ok(())
+}
+
+// The whole contents of the file at `path`
+fn read_file(vfs: &mut Vfs, path: &str) -> Result<Vec<u8>, FsError> {
+    let fd = vfs.open(path, OpenFlags::READ)?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+
+    let result = loop {
+        match vfs.read(fd, &mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(read) => data.extend_from_slice(&buf[..read]),
+            Err(err) => break Err(err),
+        }
+    };
+
+    vfs.close(fd)?;
+    result.map(|()| data)
+}
+
+// Writes `data` to the file at `path`, creating it if needed. It goes at the end of the file
+// with `append`, otherwise it replaces what was there.
+fn write_file(vfs: &mut Vfs, path: &str, data: &[u8], append: bool) -> Result<(), FsError> {
+    let mode = if append { OpenFlags::APPEND } else { OpenFlags::TRUNCATE };
+    let fd = vfs.open(path, OpenFlags::WRITE | OpenFlags::CREATE | mode)?;
+
+    let mut result = Ok(());
+    let mut written = 0;
+    while written < data.len() {
+        match vfs.write(fd, &data[written..]) {
+            Ok(0) => {
+                result = Err(FsError::NoSpace);
+                break;
+            }
+            Ok(count) => written += count,
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        }
+    }
+
+    vfs.close(fd)?;
+    result
+}
+
+// Lays out `data` like `hexdump -C`: the offset, 16 bytes in hex and the same bytes as text,
+// with a dot for anything that isn't printable
+fn hexdump(data: &[u8], out: &mut String) {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", line * 16);
+
+        for i in 0..16 {
+            if i == 8 {
+                out.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => { let _ = write!(out, " {:02x}", byte); }
+                None => out.push_str("   "),
+            }
+        }
+
+        out.push_str("  |");
+        for &byte in chunk {
+            out.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+        }
+        out.push_str("|\n");
+    }
+    let _ = writeln!(out, "{:08x}", data.len());
+}
+
+// `path` with every name as its directory has it, whatever case it was typed in or if it was the
+// short name of a long one
+fn canonical_path(vfs: &mut Vfs, path: &str) -> Result<String, FsError> {
+    let mut out = String::new();
+
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let parent = if out.is_empty() { String::from("/") } else { out.clone() };
+        let child = format!("{}/{}", out, component);
+
+        // A mount point is named by the path it was mounted on
+        let name = if vfs.is_mount_point(&child) {
+            String::from(component)
+        } else {
+            let node = vfs.stat(&child)?;
+            vfs.read_dir(&parent)?.into_iter()
+                .find(|entry| entry.node.id == node.id)
+                .map_or_else(|| String::from(component), |entry| entry.name)
+        };
+
+        out.push('/');
+        out.push_str(&name);
+    }
+
+    if out.is_empty() {
+        out.push('/');
+    }
+    Ok(out)
+}
+
+// Why the volume on / can't be taken away for a while, None if nothing is using it. The
+// directories other volumes are mounted on would be gone, and open files with them.
+fn root_in_use(vfs: &Vfs) -> Option<&'static str> {
+    if vfs.mounts().iter().any(|(path, _)| path != "/") {
+        Some("unmount the other volumes first")
+    } else if !vfs.open_files().is_empty() {
+        Some("close the open files first")
+    } else {
+        None
+    }
+}
+
+// Mounts the file system on the disk, or the partition in use, on /
+fn mount_root(vfs: &mut Vfs) -> Result<(), FsError> {
+    let volume = FatVolume::new(SharedDisk)?;
+    vfs.mount("/", Box::new(volume))
+}
+
+// Mounts a partition of the disk, named like lsblk shows it, on `path`
+fn mount_partition(vfs: &mut Vfs, name: &str, path: &str) -> Result<(), FsError> {
+    let number: usize = name.strip_prefix("part")
+        .and_then(|number| number.parse().ok())
+        .ok_or(FsError::InvalidArgument)?;
+
+    let (sectors, root_start, root_sectors) = {
+        let dev = BLOCK_DEVICE.lock();
+        (dev.inner().inner().sector_count(), dev.inner().start_lba(), dev.inner().sector_count())
+    };
+
+    let table = read_partition_table(&mut RawDisk, sectors)?.ok_or(FsError::NotFound)?;
+    let partition = table.partitions.iter()
+        .find(|partition| partition.number == number)
+        .ok_or(FsError::NotFound)?;
+
+    // The root is already mounted from it, or from sectors it shares with it
+    let end = partition.start_lba.saturating_add(partition.sector_count);
+    if partition.start_lba < root_start + root_sectors && root_start < end {
+        return Err(FsError::Busy);
+    }
+
+    let volume = FatVolume::new(Partition::new(RawDisk, partition.start_lba, partition.sector_count))?;
+    vfs.mount(path, Box::new(volume))
+}
+
@@ -0,0 +1,138 @@
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::ata::AtaDrive;
+use crate::fs::cache::CachedDevice;
+use crate::fs::error::FsError;
+use crate::fs::fat32::{BlockDevice, RamDisk};
+use crate::fs::partition::{read_partition_table, Partition};
+
+// 34 MiB, just above the smallest volume FAT32 allows
+pub const RAM_DISK_SECTORS: usize = 69632;
+
+// 128 KiB of cached sectors
+pub const CACHE_SECTORS: usize = 256;
+
+// The disk the shell works on: the ATA drive if there is one, otherwise a RAM disk that
+// is lost on reboot
+pub enum Disk {
+    Ata(AtaDrive),
+    Ram(RamDisk),
+}
+
+impl Disk {
+    // QEMU boots from the primary master, a data disk given with
+    // -drive file=disk.img,format=raw,index=1 shows up as the primary slave
+    pub fn probe() -> Self {
+        match AtaDrive::primary_slave() {
+            Some(drive) => Disk::Ata(drive),
+            None => Disk::Ram(RamDisk::blank(RAM_DISK_SECTORS)),
+        }
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        match self {
+            Disk::Ata(drive) => drive.sector_count(),
+            Disk::Ram(disk) => disk.sector_count(),
+        }
+    }
+
+    // Whether what is written survives a reboot
+    pub fn is_persistent(&self) -> bool {
+        matches!(self, Disk::Ata(_))
+    }
+}
+
+impl BlockDevice for Disk {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        match self {
+            Disk::Ata(drive) => drive.read_sector(lba, buf),
+            Disk::Ram(disk) => disk.read_sector(lba, buf),
+        }
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        match self {
+            Disk::Ata(drive) => drive.write_sector(lba, buf),
+            Disk::Ram(disk) => disk.write_sector(lba, buf),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        match self {
+            Disk::Ata(drive) => drive.sync(),
+            Disk::Ram(disk) => disk.sync(),
+        }
+    }
+}
+
+// The part of the disk the file system lives on: the first FAT partition, or the whole disk
+// if it has no partition table or the table can't be read
+fn volume(mut disk: Disk) -> Partition<Disk> {
+    let sectors = disk.sector_count();
+
+    let partition = match read_partition_table(&mut disk, sectors) {
+        Ok(Some(table)) => table.fat_partition().map(|p| (p.start_lba, p.sector_count)),
+        _ => None,
+    };
+
+    let (start_lba, sector_count) = partition.unwrap_or((0, sectors));
+    Partition::new(disk, start_lba, sector_count)
+}
+
+lazy_static! {
+    pub static ref BLOCK_DEVICE: Mutex<CachedDevice<Partition<Disk>>> = Mutex::new(
+        CachedDevice::new(volume(Disk::probe()), CACHE_SECTORS)
+    );
+}
+
+// BLOCK_DEVICE as a device of its own, taking the lock for each sector so a mounted volume
+// doesn't keep the disk locked between operations
+pub struct SharedDisk;
+
+impl BlockDevice for SharedDisk {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        BLOCK_DEVICE.lock().read_sector(lba, buf)
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        BLOCK_DEVICE.lock().write_sector(lba, buf)
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        BLOCK_DEVICE.lock().sync()
+    }
+}
+
+// The whole disk, for the partition table and the other partitions. The sectors of the
+// partition BLOCK_DEVICE is on go through its cache, so both see the same data.
+pub struct RawDisk;
+
+// Where `lba` of the disk is in BLOCK_DEVICE, if it is inside its partition
+fn cached_lba(dev: &CachedDevice<Partition<Disk>>, lba: u64) -> Option<u64> {
+    let partition = dev.inner();
+    lba.checked_sub(partition.start_lba())
+        .filter(|&offset| offset < partition.sector_count())
+}
+
+impl BlockDevice for RawDisk {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        let mut dev = BLOCK_DEVICE.lock();
+        match cached_lba(&dev, lba) {
+            Some(offset) => dev.read_sector(offset, buf),
+            None => dev.inner_mut().inner_mut().read_sector(lba, buf),
+        }
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        let mut dev = BLOCK_DEVICE.lock();
+        match cached_lba(&dev, lba) {
+            Some(offset) => dev.write_sector(offset, buf),
+            None => dev.inner_mut().inner_mut().write_sector(lba, buf),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        BLOCK_DEVICE.lock().sync()
+    }
+}
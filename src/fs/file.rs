@@ -0,0 +1,113 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::BitOr;
+
+use crate::fs::error::FsError;
+use crate::fs::vfs::Node;
+
+// Most files that can be open at the same time
+pub const MAX_OPEN_FILES: usize = 64;
+
+pub type Fd = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u8);
+
+impl OpenFlags {
+    pub const READ: Self = Self(0x01);
+    pub const WRITE: Self = Self(0x02);
+    // Create the file if it doesn't exist
+    pub const CREATE: Self = Self(0x04);
+    // Empty the file when it is opened for writing
+    pub const TRUNCATE: Self = Self(0x08);
+    // Every write goes to the end of the file, wherever the offset is
+    pub const APPEND: Self = Self(0x10);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFile {
+    // Id of the mount the file is on, which stays the same while other mounts come and go
+    pub mount_id: u64,
+    pub node: Node,
+    pub path: String,
+    pub offset: u64,
+    pub flags: OpenFlags,
+}
+
+// Open files by descriptor. A descriptor is the index of its slot, closed slots are reused
+// lowest first.
+pub struct OpenFileTable {
+    files: Vec<Option<OpenFile>>,
+}
+
+impl Default for OpenFileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenFileTable {
+    pub fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    pub fn insert(&mut self, file: OpenFile) -> Result<Fd, FsError> {
+        if let Some(fd) = self.files.iter().position(|slot| slot.is_none()) {
+            self.files[fd] = Some(file);
+            return Ok(fd);
+        }
+        if self.files.len() >= MAX_OPEN_FILES {
+            return Err(FsError::TooManyOpenFiles);
+        }
+        self.files.push(Some(file));
+        Ok(self.files.len() - 1)
+    }
+
+    pub fn get(&self, fd: Fd) -> Result<&OpenFile, FsError> {
+        self.files.get(fd)
+            .and_then(|slot| slot.as_ref())
+            .ok_or(FsError::BadDescriptor)
+    }
+
+    pub fn get_mut(&mut self, fd: Fd) -> Result<&mut OpenFile, FsError> {
+        self.files.get_mut(fd)
+            .and_then(|slot| slot.as_mut())
+            .ok_or(FsError::BadDescriptor)
+    }
+
+    pub fn remove(&mut self, fd: Fd) -> Result<OpenFile, FsError> {
+        self.files.get_mut(fd)
+            .and_then(|slot| slot.take())
+            .ok_or(FsError::BadDescriptor)
+    }
+
+    // Whether the node is open through any descriptor
+    pub fn is_open(&self, mount_id: u64, node: &Node) -> bool {
+        self.iter().any(|(_, file)| file.mount_id == mount_id && file.node.id == node.id && file.node.kind == node.kind)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Fd, &OpenFile)> {
+        self.files.iter()
+            .enumerate()
+            .filter_map(|(fd, slot)| slot.as_ref().map(|file| (fd, file)))
+    }
+}
@@ -1 +1,26 @@
-pub mod fat32;
+use core::sync::atomic::Ordering::SeqCst;
+
+use crate::debug::{debug_log, DEBUG_FS};
+use crate::print;
+use crate::rtc;
+
+pub use ::fat32;
+pub use fat32::{error, lfn, mkfs, Hooks};
+
+pub mod cache;
+pub mod commands;
+pub mod disk;
+pub mod file;
+pub mod partition;
+pub mod vfs;
+pub mod volume;
+
+// The FAT code prints to the screen, debugs into the debug view and stamps entries with the RTC
+pub const HOOKS: Hooks = Hooks {
+    log: |text| print!("{}", text),
+    debug: debug_log,
+    debug_enabled: || DEBUG_FS.load(SeqCst),
+    now: rtc::now,
+    // The RTC only counts seconds, the TSC tells apart volumes formatted in the same one
+    volume_id: || unsafe { core::arch::x86_64::_rdtsc() } as u32,
+};
@@ -0,0 +1,352 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::fs::error::FsError;
+use crate::fs::fat32::BlockDevice;
+
+const SECTOR_SIZE: usize = 512;
+
+// MBR layout
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_ENTRIES: usize = 4;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+// MBR partition types of FAT volumes
+const MBR_FAT_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+// GPT header fields
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_NAME_UNITS: usize = 36;
+// Entries are at least 128 bytes, a larger table than this is treated as corrupt
+const GPT_MAX_TABLE_SIZE: usize = 1024 * 1024;
+
+// EBD0A0A2-B9E5-4433-87C0-68B6B72699C7 as stored on disk, with the first three fields little endian
+const GPT_BASIC_DATA: [u8; 16] = [
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+    0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+];
+// C12A7328-F81F-11D2-BA4B-00A0C93EC93B, the EFI system partition is FAT as well
+const GPT_EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11,
+    0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Mbr,
+    Gpt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    Mbr(u8),
+    Gpt([u8; 16]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    // 1-based, in table order
+    pub number: usize,
+    pub start_lba: u64,
+    pub sector_count: u64,
+    pub kind: PartitionType,
+    // Only GPT partitions have names
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionTable {
+    pub scheme: Scheme,
+    pub partitions: Vec<PartitionInfo>,
+}
+
+impl PartitionInfo {
+    // Whether the type says it holds a FAT file system
+    pub fn is_fat(&self) -> bool {
+        match self.kind {
+            PartitionType::Mbr(kind) => MBR_FAT_TYPES.contains(&kind),
+            PartitionType::Gpt(guid) => guid == GPT_BASIC_DATA || guid == GPT_EFI_SYSTEM,
+        }
+    }
+}
+
+impl PartitionTable {
+    // The partition a FAT file system is mounted from: the first FAT one, or the first one at all
+    pub fn fat_partition(&self) -> Option<&PartitionInfo> {
+        self.partitions.iter()
+            .find(|partition| partition.is_fat())
+            .or_else(|| self.partitions.first())
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::Mbr => write!(f, "MBR"),
+            Scheme::Gpt => write!(f, "GPT"),
+        }
+    }
+}
+
+impl fmt::Display for PartitionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PartitionType::Mbr(0x0B) | PartitionType::Mbr(0x0C) => write!(f, "FAT32"),
+            PartitionType::Mbr(0x04) | PartitionType::Mbr(0x06) | PartitionType::Mbr(0x0E) => write!(f, "FAT16"),
+            PartitionType::Mbr(0x01) => write!(f, "FAT12"),
+            PartitionType::Mbr(0x07) => write!(f, "NTFS"),
+            PartitionType::Mbr(0x83) => write!(f, "Linux"),
+            PartitionType::Mbr(kind) => write!(f, "0x{:02X}", kind),
+            PartitionType::Gpt(GPT_BASIC_DATA) => write!(f, "Basic data"),
+            PartitionType::Gpt(GPT_EFI_SYSTEM) => write!(f, "EFI system"),
+            PartitionType::Gpt(guid) => {
+                // Mixed endian, like the GUID is usually written
+                write!(
+                    f,
+                    "{:08X}-{:04X}-{:04X}-",
+                    u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+                    u16::from_le_bytes([guid[4], guid[5]]),
+                    u16::from_le_bytes([guid[6], guid[7]]),
+                )?;
+                for (i, b) in guid[8..].iter().enumerate() {
+                    if i == 2 {
+                        write!(f, "-")?;
+                    }
+                    write!(f, "{:02X}", b)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// A slice of another device. Sector 0 is the first sector of the partition and nothing
+// outside of it can be read or written.
+pub struct Partition<D: BlockDevice> {
+    device: D,
+    start_lba: u64,
+    sector_count: u64,
+}
+
+impl<D: BlockDevice> Partition<D> {
+    pub fn new(device: D, start_lba: u64, sector_count: u64) -> Self {
+        Self { device, start_lba, sector_count }
+    }
+
+    pub fn start_lba(&self) -> u64 {
+        self.start_lba
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    fn device_lba(&self, lba: u64) -> Result<u64, FsError> {
+        if lba >= self.sector_count {
+            return Err(FsError::Io);
+        }
+        Ok(self.start_lba + lba)
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for Partition<D> {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        let lba = self.device_lba(lba)?;
+        self.device.read_sector(lba, buf)
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        let lba = self.device_lba(lba)?;
+        self.device.write_sector(lba, buf)
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        self.device.sync()
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// CRC-32 as used by GPT (and zlib), bit by bit since it is only run on a few sectors
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// A FAT boot sector also ends in 0x55AA, its file system type tells it apart from an MBR
+fn is_fat_boot_sector(sector: &[u8; SECTOR_SIZE]) -> bool {
+    let jump = sector[0] == 0xEB || sector[0] == 0xE9;
+    jump && (&sector[82..87] == b"FAT32" || &sector[54..57] == b"FAT")
+}
+
+// Reads the partition table of a disk of `total_sectors` sectors. A disk without one, like a
+// FAT volume that starts at sector 0, gives `None`.
+pub fn read_partition_table<D: BlockDevice>(device: &mut D, total_sectors: u64) -> Result<Option<PartitionTable>, FsError> {
+    let mut mbr = [0u8; SECTOR_SIZE];
+    device.read_sector(0, &mut mbr)?;
+
+    if mbr[510] != 0x55 || mbr[511] != 0xAA || is_fat_boot_sector(&mbr) {
+        return Ok(None);
+    }
+
+    let mut partitions = Vec::new();
+    for i in 0..MBR_ENTRIES {
+        let entry = &mbr[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+
+        // Only 0x00 and 0x80 are valid boot flags, anything else is boot code and not a table
+        if entry[0] & 0x7F != 0 {
+            return Ok(None);
+        }
+
+        let kind = entry[4];
+        let start_lba = read_u32(entry, 8) as u64;
+        let sector_count = read_u32(entry, 12) as u64;
+
+        if kind == MBR_TYPE_GPT_PROTECTIVE {
+            return read_gpt(device, total_sectors).map(Some);
+        }
+        if kind == 0 || sector_count == 0 {
+            continue;
+        }
+        if start_lba == 0 || start_lba + sector_count > total_sectors {
+            return Err(FsError::Corrupt);
+        }
+
+        partitions.push(PartitionInfo {
+            number: i + 1,
+            start_lba,
+            sector_count,
+            kind: PartitionType::Mbr(kind),
+            name: String::new(),
+        });
+    }
+
+    if partitions.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(PartitionTable { scheme: Scheme::Mbr, partitions }))
+}
+
+// The primary header is at LBA 1 and the backup in the last sector, the backup is only used
+// when the primary one is damaged
+fn read_gpt<D: BlockDevice>(device: &mut D, total_sectors: u64) -> Result<PartitionTable, FsError> {
+    let partitions = match read_gpt_at(device, 1, total_sectors) {
+        Ok(partitions) => partitions,
+        Err(_) => {
+            let backup_lba = total_sectors.checked_sub(1).ok_or(FsError::Corrupt)?;
+            read_gpt_at(device, backup_lba, total_sectors)?
+        }
+    };
+
+    Ok(PartitionTable { scheme: Scheme::Gpt, partitions })
+}
+
+fn read_gpt_at<D: BlockDevice>(device: &mut D, header_lba: u64, total_sectors: u64) -> Result<Vec<PartitionInfo>, FsError> {
+    let mut header = [0u8; SECTOR_SIZE];
+    device.read_sector(header_lba, &mut header)?;
+
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(FsError::Corrupt);
+    }
+
+    let header_size = read_u32(&header, 12) as usize;
+    if !(GPT_MIN_HEADER_SIZE..=SECTOR_SIZE).contains(&header_size) {
+        return Err(FsError::Corrupt);
+    }
+
+    // The checksum is computed with its own field zeroed
+    let stored_crc = read_u32(&header, 16);
+    let mut checked = [0u8; SECTOR_SIZE];
+    checked[..header_size].copy_from_slice(&header[..header_size]);
+    checked[16..20].fill(0);
+    if crc32(&checked[..header_size]) != stored_crc || read_u64(&header, 24) != header_lba {
+        return Err(FsError::Corrupt);
+    }
+
+    let first_usable = read_u64(&header, 40);
+    let last_usable = read_u64(&header, 48);
+    let entries_lba = read_u64(&header, 72);
+    let entry_count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    let entries_crc = read_u32(&header, 88);
+
+    if entry_size < 128 || entry_size % 8 != 0 || last_usable >= total_sectors || first_usable > last_usable {
+        return Err(FsError::Corrupt);
+    }
+
+    let table_size = entry_count.checked_mul(entry_size).ok_or(FsError::Corrupt)?;
+    if table_size > GPT_MAX_TABLE_SIZE {
+        return Err(FsError::Corrupt);
+    }
+
+    let table_sectors = table_size.div_ceil(SECTOR_SIZE) as u64;
+    if entries_lba.checked_add(table_sectors).is_none_or(|end| end > total_sectors) {
+        return Err(FsError::Corrupt);
+    }
+
+    let mut table = vec![0u8; table_sectors as usize * SECTOR_SIZE];
+    for (i, chunk) in table.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+        device.read_sector(entries_lba + i as u64, chunk.try_into().unwrap())?;
+    }
+
+    if crc32(&table[..table_size]) != entries_crc {
+        return Err(FsError::Corrupt);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in table[..table_size].chunks_exact(entry_size).enumerate() {
+        let guid: [u8; 16] = entry[0..16].try_into().unwrap();
+        if guid == [0; 16] {
+            continue;
+        }
+
+        // The last LBA is inclusive
+        let start_lba = read_u64(entry, 32);
+        let end_lba = read_u64(entry, 40);
+        if start_lba < first_usable || end_lba > last_usable || end_lba < start_lba {
+            return Err(FsError::Corrupt);
+        }
+
+        let units: Vec<u16> = entry[56..56 + GPT_NAME_UNITS * 2]
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+
+        partitions.push(PartitionInfo {
+            number: i + 1,
+            start_lba,
+            sector_count: end_lba - start_lba + 1,
+            kind: PartitionType::Gpt(guid),
+            name: String::from_utf16_lossy(&units),
+        });
+    }
+
+    Ok(partitions)
+}
@@ -0,0 +1,423 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::fs::error::FsError;
+use crate::fs::file::{Fd, OpenFile, OpenFileTable, OpenFlags, SeekFrom};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+}
+
+// Handle to a file or directory of one file system. The id only means something to the
+// file system that gave it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Node {
+    pub id: u64,
+    pub kind: NodeKind,
+    pub size: u64,
+}
+
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        self.kind == NodeKind::Directory
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub node: Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStats {
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+}
+
+// What a file system has to provide to be mounted. Names are single path components,
+// never paths, and directories don't list "." or "..".
+pub trait FileSystemOps: Send {
+    fn fs_type(&self) -> &'static str;
+    fn root(&mut self) -> Result<Node, FsError>;
+    fn lookup(&mut self, dir: &Node, name: &str) -> Result<Node, FsError>;
+    fn readdir(&mut self, dir: &Node) -> Result<Vec<Entry>, FsError>;
+    fn create(&mut self, dir: &Node, name: &str, kind: NodeKind) -> Result<Node, FsError>;
+    // Removes a file or an empty directory
+    fn unlink(&mut self, dir: &Node, name: &str) -> Result<(), FsError>;
+    fn rename(&mut self, src_dir: &Node, old_name: &str, dst_dir: &Node, new_name: &str) -> Result<(), FsError>;
+    // The node as it is now, the size of a file changes as it is written
+    fn refresh(&mut self, node: &Node) -> Result<Node, FsError>;
+    // Reads from byte `offset` of a file, 0 bytes read means the end of the file
+    fn read(&mut self, file: &Node, offset: u64, buf: &mut [u8]) -> Result<usize, FsError>;
+    // Writes at byte `offset` of a file, growing it as needed
+    fn write(&mut self, file: &Node, offset: u64, data: &[u8]) -> Result<usize, FsError>;
+    // Shortens a file to `size` bytes
+    fn truncate(&mut self, file: &Node, size: u64) -> Result<(), FsError>;
+    fn stats(&mut self) -> Result<FsStats, FsError>;
+    fn sync(&mut self) -> Result<(), FsError>;
+}
+
+struct Mount {
+    id: u64,
+    path: String,
+    fs: Box<dyn FileSystemOps>,
+}
+
+// The mount table and the open files. Every path is absolute and goes to the file system
+// mounted on its longest prefix.
+pub struct Vfs {
+    mounts: Vec<Mount>,
+    next_mount_id: u64,
+    files: OpenFileTable,
+}
+
+lazy_static! {
+    pub static ref VFS: Mutex<Vfs> = Mutex::new(Vfs::new());
+}
+
+// Turns `path` into an absolute path without ".", ".." or repeated slashes. Relative paths
+// start at `cwd`, which must be absolute. ".." of the root is the root.
+pub fn normalize(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+
+    let start = if path.starts_with('/') { "" } else { cwd };
+    for component in start.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+
+    let mut out = String::new();
+    for component in components {
+        out.push('/');
+        out.push_str(component);
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    out
+}
+
+// Splits a normalized path into its parent and last component. The root has neither.
+pub fn split_parent(path: &str) -> Result<(&str, &str), FsError> {
+    let idx = path.rfind('/').ok_or(FsError::InvalidArgument)?;
+    let name = &path[idx + 1..];
+    if name.is_empty() {
+        return Err(FsError::InvalidArgument);
+    }
+
+    let parent = if idx == 0 { "/" } else { &path[..idx] };
+    Ok((parent, name))
+}
+
+// The part of `path` below the mount point `mount`, or None if it isn't below it
+fn strip_mount<'p>(mount: &str, path: &'p str) -> Option<&'p str> {
+    if mount == "/" {
+        return Some(path);
+    }
+    let rest = path.strip_prefix(mount)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self {
+            mounts: Vec::new(),
+            next_mount_id: 0,
+            files: OpenFileTable::new(),
+        }
+    }
+
+    // Mounts `fs` on `path`. Anything but the root has to be an existing directory.
+    pub fn mount(&mut self, path: &str, fs: Box<dyn FileSystemOps>) -> Result<(), FsError> {
+        let path = normalize("/", path);
+
+        if self.is_mount_point(&path) {
+            return Err(FsError::Busy);
+        }
+        if path != "/" && !self.stat(&path)?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+
+        self.mounts.push(Mount { id: self.next_mount_id, path, fs });
+        self.next_mount_id += 1;
+        Ok(())
+    }
+
+    // Takes the file system off `path` and gives it back, after writing out what it has cached
+    pub fn unmount(&mut self, path: &str) -> Result<Box<dyn FileSystemOps>, FsError> {
+        let path = normalize("/", path);
+        let idx = self.mounts.iter()
+            .position(|mount| mount.path == path)
+            .ok_or(FsError::InvalidArgument)?;
+
+        // Nothing can be mounted inside it or be open on it
+        let id = self.mounts[idx].id;
+        if self.has_mounts_below(&path) || self.files.iter().any(|(_, file)| file.mount_id == id) {
+            return Err(FsError::Busy);
+        }
+
+        self.mounts[idx].fs.sync()?;
+        Ok(self.mounts.remove(idx).fs)
+    }
+
+    pub fn is_mount_point(&self, path: &str) -> bool {
+        self.mounts.iter().any(|mount| mount.path == path)
+    }
+
+    fn has_mounts_below(&self, path: &str) -> bool {
+        self.mounts.iter()
+            .any(|mount| mount.path != path && strip_mount(path, &mount.path).is_some())
+    }
+
+    // Mount points and the type of what is mounted on them
+    pub fn mounts(&self) -> Vec<(String, &'static str)> {
+        self.mounts.iter()
+            .map(|mount| (mount.path.clone(), mount.fs.fs_type()))
+            .collect()
+    }
+
+    // Index of the mount `path` is on, and the rest of the path inside it
+    fn find_mount<'p>(&self, path: &'p str) -> Result<(usize, &'p str), FsError> {
+        self.mounts.iter()
+            .enumerate()
+            .filter_map(|(idx, mount)| strip_mount(&mount.path, path).map(|rest| (idx, mount.path.len(), rest)))
+            .max_by_key(|&(_, len, _)| len)
+            .map(|(idx, _, rest)| (idx, rest))
+            .ok_or(FsError::NotFound)
+    }
+
+    // Walks an absolute path down from the root of the file system it is on
+    fn locate(&mut self, path: &str) -> Result<(usize, Node), FsError> {
+        let path = normalize("/", path);
+        let (idx, rest) = self.find_mount(&path)?;
+        let fs = &mut self.mounts[idx].fs;
+
+        let mut node = fs.root()?;
+        for name in rest.split('/').filter(|name| !name.is_empty()) {
+            if !node.is_dir() {
+                return Err(FsError::NotADirectory);
+            }
+            node = fs.lookup(&node, name)?;
+        }
+        Ok((idx, node))
+    }
+
+    // The parent directory of `path` and the name it has in there
+    fn locate_parent<'p>(&mut self, path: &'p str) -> Result<(usize, Node, &'p str), FsError> {
+        let (parent, name) = split_parent(path)?;
+        let (idx, dir) = self.locate(parent)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        Ok((idx, dir, name))
+    }
+
+    pub fn stat(&mut self, path: &str) -> Result<Node, FsError> {
+        self.locate(path).map(|(_, node)| node)
+    }
+
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<Entry>, FsError> {
+        let (idx, dir) = self.locate(path)?;
+        if !dir.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        self.mounts[idx].fs.readdir(&dir)
+    }
+
+    pub fn create(&mut self, path: &str, kind: NodeKind) -> Result<Node, FsError> {
+        let path = normalize("/", path);
+        let (idx, dir, name) = self.locate_parent(&path)?;
+        self.mounts[idx].fs.create(&dir, name, kind)
+    }
+
+    // Removes a file or an empty directory, or with `recursive` a directory and all it holds.
+    // Mount points and open files can't be removed.
+    pub fn remove(&mut self, path: &str, recursive: bool) -> Result<(), FsError> {
+        let path = normalize("/", path);
+        if self.is_mount_point(&path) {
+            return Err(FsError::Busy);
+        }
+
+        let (idx, dir, name) = self.locate_parent(&path)?;
+        let node = self.mounts[idx].fs.lookup(&dir, name)?;
+        if self.files.is_open(self.mounts[idx].id, &node) {
+            return Err(FsError::Busy);
+        }
+
+        if node.is_dir() && recursive {
+            for entry in self.mounts[idx].fs.readdir(&node)? {
+                self.remove(&format!("{}/{}", path, entry.name), true)?;
+            }
+        }
+
+        self.mounts[idx].fs.unlink(&dir, name)
+    }
+
+    // Renames or moves an entry, `to` is the full new path. Both have to be on the same mount,
+    // and an open file can't be moved because its node would change.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), FsError> {
+        let from = normalize("/", from);
+        let to = normalize("/", to);
+        if self.is_mount_point(&from) || self.is_mount_point(&to) || self.has_mounts_below(&from) {
+            return Err(FsError::Busy);
+        }
+
+        let (src_idx, src_dir, old_name) = self.locate_parent(&from)?;
+        let (dst_idx, dst_dir, new_name) = self.locate_parent(&to)?;
+        if src_idx != dst_idx {
+            return Err(FsError::CrossDevice);
+        }
+
+        let node = self.mounts[src_idx].fs.lookup(&src_dir, old_name)?;
+        if self.files.is_open(self.mounts[src_idx].id, &node) {
+            return Err(FsError::Busy);
+        }
+
+        self.mounts[src_idx].fs.rename(&src_dir, old_name, &dst_dir, new_name)
+    }
+
+    pub fn stats(&mut self, mount_path: &str) -> Result<FsStats, FsError> {
+        self.mounts.iter_mut()
+            .find(|mount| mount.path == mount_path)
+            .ok_or(FsError::NotFound)?
+            .fs
+            .stats()
+    }
+
+    // Syncs every mounted file system, the first error is returned once all were tried
+    pub fn sync(&mut self) -> Result<(), FsError> {
+        let mut result = Ok(());
+        for mount in self.mounts.iter_mut() {
+            let synced = mount.fs.sync();
+            if result.is_ok() {
+                result = synced;
+            }
+        }
+        result
+    }
+
+    fn mount_index(&self, mount_id: u64) -> Result<usize, FsError> {
+        self.mounts.iter()
+            .position(|mount| mount.id == mount_id)
+            .ok_or(FsError::BadDescriptor)
+    }
+
+    // Opens a file and gives back its descriptor, with the offset at the start of the file
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Result<Fd, FsError> {
+        let path = normalize("/", path);
+
+        let (idx, mut node) = match self.locate(&path) {
+            Err(FsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                let (idx, dir, name) = self.locate_parent(&path)?;
+                let node = self.mounts[idx].fs.create(&dir, name, NodeKind::File)?;
+                (idx, node)
+            }
+            other => other?,
+        };
+        if node.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+
+        if flags.contains(OpenFlags::WRITE | OpenFlags::TRUNCATE) && node.size != 0 {
+            self.mounts[idx].fs.truncate(&node, 0)?;
+            node = self.mounts[idx].fs.refresh(&node)?;
+        }
+
+        self.files.insert(OpenFile {
+            mount_id: self.mounts[idx].id,
+            node,
+            path,
+            offset: 0,
+            flags,
+        })
+    }
+
+    // Reads from the offset of `fd` and moves it past what was read
+    pub fn read(&mut self, fd: Fd, buf: &mut [u8]) -> Result<usize, FsError> {
+        let file = self.files.get(fd)?;
+        if !file.flags.contains(OpenFlags::READ) {
+            return Err(FsError::BadDescriptor);
+        }
+
+        let idx = self.mount_index(file.mount_id)?;
+        let (node, offset) = (file.node, file.offset);
+        let read = self.mounts[idx].fs.read(&node, offset, buf)?;
+
+        self.files.get_mut(fd)?.offset += read as u64;
+        Ok(read)
+    }
+
+    // Writes at the offset of `fd`, or at the end of the file if it was opened to append
+    pub fn write(&mut self, fd: Fd, data: &[u8]) -> Result<usize, FsError> {
+        let file = self.files.get(fd)?;
+        if !file.flags.contains(OpenFlags::WRITE) {
+            return Err(FsError::BadDescriptor);
+        }
+
+        let idx = self.mount_index(file.mount_id)?;
+        let (node, mut offset) = (file.node, file.offset);
+        if file.flags.contains(OpenFlags::APPEND) {
+            offset = self.mounts[idx].fs.refresh(&node)?.size;
+        }
+
+        let written = self.mounts[idx].fs.write(&node, offset, data)?;
+
+        self.files.get_mut(fd)?.offset = offset + written as u64;
+        Ok(written)
+    }
+
+    // Moves the offset of `fd` and returns the new one. It can go past the end of the file,
+    // a write there leaves a gap of zeros.
+    pub fn seek(&mut self, fd: Fd, pos: SeekFrom) -> Result<u64, FsError> {
+        let file = self.files.get(fd)?;
+        let idx = self.mount_index(file.mount_id)?;
+
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (file.offset, delta),
+            SeekFrom::End(delta) => {
+                let node = file.node;
+                (self.mounts[idx].fs.refresh(&node)?.size, delta)
+            }
+        };
+
+        let offset = base.checked_add_signed(delta).ok_or(FsError::InvalidArgument)?;
+        self.files.get_mut(fd)?.offset = offset;
+        Ok(offset)
+    }
+
+    pub fn close(&mut self, fd: Fd) -> Result<(), FsError> {
+        self.files.remove(fd).map(|_| ())
+    }
+
+    // Descriptors that are open and the file each one is for
+    pub fn open_files(&self) -> Vec<(Fd, OpenFile)> {
+        self.files.iter()
+            .map(|(fd, file)| (fd, file.clone()))
+            .collect()
+    }
+}
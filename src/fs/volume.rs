@@ -0,0 +1,151 @@
+use alloc::vec::Vec;
+
+use crate::fs::error::FsError;
+use crate::fs::fat32::{is_dot_entry, BlockDevice, DirEntry, FatType, FileSystem};
+use crate::fs::vfs::{Entry, FileSystemOps, FsStats, Node, NodeKind};
+use crate::fs::HOOKS;
+
+// A FAT volume as the VFS sees it. The device is owned and the FAT state is read again for
+// every operation, so nothing goes stale when the volume is formatted or checked directly.
+//
+// Directories are identified by their first cluster and files by the position of their entry.
+pub struct FatVolume<D: BlockDevice> {
+    device: D,
+    fat_type: FatType,
+}
+
+impl<D: BlockDevice> FatVolume<D> {
+    // Fails if there is no FAT file system on the device
+    pub fn new(mut device: D) -> Result<Self, FsError> {
+        let fat_type = FileSystem::new(&mut device, HOOKS)?.fat_type();
+        Ok(Self { device, fat_type })
+    }
+
+    fn fs(&mut self) -> Result<FileSystem<'_, D>, FsError> {
+        FileSystem::new(&mut self.device, HOOKS)
+    }
+}
+
+fn node(pos: u64, entry: &DirEntry, root_cluster: u32) -> Node {
+    if entry.is_directory() {
+        // ".." of a directory in the root stores 0
+        let cluster = match entry.first_cluster() {
+            0 => root_cluster,
+            cluster => cluster,
+        };
+        Node { id: cluster as u64, kind: NodeKind::Directory, size: 0 }
+    } else {
+        Node { id: pos, kind: NodeKind::File, size: entry.file_size as u64 }
+    }
+}
+
+// The cluster of a directory node
+fn dir_cluster(dir: &Node) -> Result<u32, FsError> {
+    if !dir.is_dir() {
+        return Err(FsError::NotADirectory);
+    }
+    Ok(dir.id as u32)
+}
+
+impl<D: BlockDevice + Send> FileSystemOps for FatVolume<D> {
+    fn fs_type(&self) -> &'static str {
+        self.fat_type.name()
+    }
+
+    fn root(&mut self) -> Result<Node, FsError> {
+        let root = self.fs()?.root_cluster();
+        Ok(Node { id: root as u64, kind: NodeKind::Directory, size: 0 })
+    }
+
+    fn lookup(&mut self, dir: &Node, name: &str) -> Result<Node, FsError> {
+        let cluster = dir_cluster(dir)?;
+        let mut fs = self.fs()?;
+        let (pos, entry) = fs.find_entry(cluster, name)?;
+        Ok(node(pos, &entry, fs.root_cluster()))
+    }
+
+    fn readdir(&mut self, dir: &Node) -> Result<Vec<Entry>, FsError> {
+        let cluster = dir_cluster(dir)?;
+        let mut fs = self.fs()?;
+        let root = fs.root_cluster();
+
+        let mut entries = Vec::new();
+        for (pos, entry) in fs.read_dir_positions(cluster)? {
+            if is_dot_entry(&entry) {
+                continue;
+            }
+            let name = entry.get_name().map_err(|_| FsError::Corrupt)?;
+            entries.push(Entry { node: node(pos, &entry, root), name });
+        }
+        Ok(entries)
+    }
+
+    fn create(&mut self, dir: &Node, name: &str, kind: NodeKind) -> Result<Node, FsError> {
+        let cluster = dir_cluster(dir)?;
+        let mut fs = self.fs()?;
+        match kind {
+            NodeKind::File => fs.create_file(cluster, name)?,
+            NodeKind::Directory => fs.create_dir(cluster, name)?,
+        }
+
+        let (pos, entry) = fs.find_entry(cluster, name)?;
+        Ok(node(pos, &entry, fs.root_cluster()))
+    }
+
+    fn unlink(&mut self, dir: &Node, name: &str) -> Result<(), FsError> {
+        let cluster = dir_cluster(dir)?;
+        let mut fs = self.fs()?;
+        match fs.remove(cluster, name) {
+            Err(FsError::IsADirectory) => fs.remove_dir(cluster, name, false),
+            other => other,
+        }
+    }
+
+    fn rename(&mut self, src_dir: &Node, old_name: &str, dst_dir: &Node, new_name: &str) -> Result<(), FsError> {
+        let src = dir_cluster(src_dir)?;
+        let dst = dir_cluster(dst_dir)?;
+        self.fs()?.rename(src, old_name, dst, new_name)
+    }
+
+    fn refresh(&mut self, node: &Node) -> Result<Node, FsError> {
+        if node.is_dir() {
+            return Ok(*node);
+        }
+        let entry = self.fs()?.file_at(node.id)?;
+        Ok(Node { size: entry.file_size as u64, ..*node })
+    }
+
+    fn read(&mut self, file: &Node, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        if file.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        self.fs()?.read_at(file.id, offset, buf)
+    }
+
+    fn write(&mut self, file: &Node, offset: u64, data: &[u8]) -> Result<usize, FsError> {
+        if file.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        self.fs()?.write_at(file.id, offset, data)
+    }
+
+    fn truncate(&mut self, file: &Node, size: u64) -> Result<(), FsError> {
+        if file.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        self.fs()?.truncate_at(file.id, size)
+    }
+
+    fn stats(&mut self) -> Result<FsStats, FsError> {
+        let mut fs = self.fs()?;
+        Ok(FsStats {
+            block_size: fs.cluster_size() as u64,
+            total_blocks: fs.cluster_count() as u64,
+            free_blocks: fs.free_clusters()? as u64,
+        })
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        self.device.sync()
+    }
+}
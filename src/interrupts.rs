@@ -1,19 +1,15 @@
 use core::fmt::Write;
 
 use crate::DEBUG_MODE;
-use crate::shell::EXECUTE_COMMAND;
 use core::sync::atomic::Ordering::SeqCst;
 
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 use crate::println;
-use crate::print;
 
 
-use alloc::string::String;
 use lazy_static::lazy_static;
 use crate::gdt;
-use crate::shell::TERMINAL;
-use crate::vga_buffer::WRITER;
+use crate::shell::{self, Key};
 
 use pic8259::ChainedPics;
 use spin;
@@ -112,7 +108,7 @@ extern "x86-interrupt" fn page_fault_handler(
 extern "x86-interrupt" fn keyboard_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+    use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet1};
     use spin::Mutex;
     use x86_64::instructions::port::Port;
 
@@ -137,17 +133,30 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
                     }
                 }
                 else {
-                    let mut term = TERMINAL.lock();
-                    if character == '\u{8}' {
-                        term.pop_char();
-                        WRITER.lock().delete_byte();
-                    }
-                    else{
-                        let mut result: String = term.push_char(byte);
-                        if character == '\n' || character == '\r' {
-                            EXECUTE_COMMAND.store(true, SeqCst);
-                        }
-                            print!("{}", result);
+                    // Like the other keys, left for the shell loop
+                    shell::queue_key(match character {
+                        '\n' | '\r' => Key::Enter,
+                        '\t' => Key::Tab,
+                        '\u{8}' => Key::Backspace,
+                        '\u{7f}' => Key::Delete,
+                        _ => Key::Char(byte),
+                    });
+                }
+            }
+            else if let DecodedKey::RawKey(key) = decoded {
+                if !DEBUG_MODE.load(SeqCst) {
+                    // The shell loop moves through the line, the terminal may be busy running a command
+                    let key = match key {
+                        KeyCode::ArrowUp => Some(Key::Up),
+                        KeyCode::ArrowDown => Some(Key::Down),
+                        KeyCode::ArrowLeft => Some(Key::Left),
+                        KeyCode::ArrowRight => Some(Key::Right),
+                        KeyCode::Home => Some(Key::Home),
+                        KeyCode::End => Some(Key::End),
+                        _ => None,
+                    };
+                    if let Some(key) = key {
+                        shell::queue_key(key);
                     }
                 }
             }
@@ -7,11 +7,9 @@
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-use crate::shell::TERMINAL;
 use core::fmt::Write;
 
 use core::panic::PanicInfo;
-use alloc::vec::Vec;
 use crate::serial::Green;
 use crate::serial::Red;
 
@@ -30,14 +28,15 @@ pub mod interrupts;
 
 pub mod memory;
 
+pub mod rtc;
+pub mod ata;
+
 #[cfg(test)]
 use bootloader::{entry_point, BootInfo};
 
 #[cfg(test)]
 entry_point!(test_kernel_main);
 
-use core::sync::atomic::Ordering::SeqCst;
-
 use core::sync::atomic::{AtomicBool};
 pub static DEBUG_MODE: AtomicBool = AtomicBool::new(false);
 
@@ -51,35 +50,8 @@ pub fn init() {
 pub fn hlt_loop() -> ! {
     loop {
         x86_64::instructions::hlt();
-        if shell::EXECUTE_COMMAND.load(SeqCst){
-            let mut term = TERMINAL.lock();
-            let result = term.execute_command();
-                print!("{}", result);
-            shell::EXECUTE_COMMAND.store(false, SeqCst);
-        }
-    }
-}
-
-pub fn str_to_fat_name(filename: &str) -> [u8; 11] {
-    let mut fat_name = [b' '; 11];
-
-    let parts: Vec<&str> = filename.split('.').collect();
-
-    if let Some(name) = parts.get(0) {
-        for (i, &b) in name.as_bytes().iter().take(8).enumerate() {
-            fat_name[i] = b.to_ascii_uppercase();
-        }
+        shell::handle_keys();
     }
-
-    if parts.len() > 1 {
-        if let Some(ext) = parts.get(1) {
-            for (i, &b) in ext.as_bytes().iter().take(3).enumerate() {
-                fat_name[8 + i] = b.to_ascii_uppercase();
-            }
-        }
-    }
-
-    fat_name
 }
 
 pub trait Testable {
@@ -7,11 +7,16 @@
 
 extern crate alloc;
 
-use ups::fs::fat32::{BLOCK_DEVICE, FileSystem};
+use ups::fs::disk::{BLOCK_DEVICE, Disk, SharedDisk};
+use ups::fs::fat32::{BlockDevice, FileSystem};
+use ups::fs::{mkfs, HOOKS};
+use ups::fs::vfs::VFS;
+use ups::fs::volume::FatVolume;
 use ups::vga_buffer::disable_hardware_cursor;
 use ups::shell;
 use ups::interrupts;
 
+use alloc::boxed::Box;
 use core::panic::PanicInfo;
 use ups::{allocator, println};
 
@@ -45,36 +50,62 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     {
         let mut block_device = BLOCK_DEVICE.lock();
+        let sectors = block_device.inner().sector_count();
+        let persistent = block_device.inner().inner().is_persistent();
 
-        // Create the filesystem using the locked &mut RamDisk
-        let mut fs = FileSystem::new(&mut *block_device).expect("Failed to create FS");
+        let mut boot_sector = [0u8; 512];
+        block_device.read_sector(0, &mut boot_sector).expect("Failed to read the disk");
 
-        println!("FAT starts at sector {}", fs.fat_start);
+        // Only a RAM disk is formatted here, a real disk is left for mkfs even when it is empty
+        let blank = !persistent && boot_sector.iter().all(|&b| b == 0);
 
-        let occupied = fs.count_occupied_clusters();
-        println!("Occupied clusters: {}", occupied);
+        match block_device.inner().inner() {
+            Disk::Ata(drive) => println!("Disk: {}, {} MB", drive.model(), drive.sector_count() / 2048),
+            Disk::Ram(_) => println!("No disk found, using a RAM disk of {} MB", sectors / 2048),
+        }
 
-        fs.init_fats();
-        println!("FAT was just set up");
+        let start_lba = block_device.inner().start_lba();
+        if start_lba != 0 {
+            println!("Using the partition at sector {}, {} MB", start_lba, sectors / 2048);
+        }
 
-        let occupied = fs.count_occupied_clusters();
-        println!("Occupied clusters: {}", occupied);
+        if blank {
+            mkfs::format(&mut *block_device, sectors, None, HOOKS).expect("Failed to format the disk");
+        }
 
-        fs.create_root_dir().unwrap();
+        match FileSystem::new(&mut *block_device, HOOKS) {
+            Ok(mut fs) => {
+                println!("FAT starts at sector {}", fs.fat_start);
 
-        let occupied = fs.count_occupied_clusters();
-        println!("Occupied clusters: {}", occupied);
+                let occupied = fs.count_occupied_clusters().unwrap();
+                println!("Occupied clusters: {}", occupied);
 
+                // Some files to play with on a fresh RAM disk
+                if blank {
+                    fs.create_file(2u32,&"Hellowo.rld").unwrap();
+                    fs.create_dir(2u32,&"Hellodir").unwrap();
 
-        fs.create_file(2u32,&"Hellowo.rld").unwrap();
-        fs.create_dir(2u32,&"Hellodir").unwrap();
+                    let occupied = fs.count_occupied_clusters().unwrap();
+                    println!("Occupied clusters: {}", occupied);
+                }
+            }
+            Err(err) => println!("The disk has no FAT file system ({}), use mkfs to format it", err),
+        }
 
-        let occupied = fs.count_occupied_clusters();
-        println!("Occupied clusters: {}", occupied);
+        block_device.sync().expect("Failed to write to the disk");
+    }
+
+    // The volume takes the disk lock for every sector, so it is mounted once the lock is released
+    if let Ok(volume) = FatVolume::new(SharedDisk) {
+        VFS.lock().mount("/", Box::new(volume)).expect("Failed to mount the root file system");
+        println!("Mounted the disk on /");
     }
 
     disable_hardware_cursor();
 
+    ups::fs::commands::register_commands();
+    ups::rtc::register_commands();
+    ups::debug::register_commands();
     shell::init();
 
     interrupts::unmask_irq1();
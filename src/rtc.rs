@@ -0,0 +1,172 @@
+use core::fmt::Write;
+
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+pub use fat32::DateTime;
+
+use crate::shell::{self, Command, Context, UsageError};
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+// Not part of the original MC146818, but QEMU and most ACPI machines have it
+const REG_CENTURY: u8 = 0x32;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+// Status A: the clock is updating and the time registers must not be read
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+// Status B: values are binary instead of BCD, hours go 0-23 instead of 1-12 with a PM bit
+const BINARY_MODE: u8 = 0x04;
+const HOUR_24_MODE: u8 = 0x02;
+const HOUR_PM: u8 = 0x80;
+
+// Registers as the chip reports them, before BCD and 12-hour decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn read_register(reg: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS);
+    let mut data: Port<u8> = Port::new(CMOS_DATA);
+
+    unsafe {
+        address.write(reg);
+        data.read()
+    }
+}
+
+fn read_raw() -> RawTime {
+    while read_register(REG_STATUS_A) & UPDATE_IN_PROGRESS != 0 {}
+
+    RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: read_register(REG_CENTURY),
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+fn decode(raw: RawTime, status_b: u8) -> DateTime {
+    let binary = status_b & BINARY_MODE != 0;
+    let convert = |value: u8| if binary { value } else { from_bcd(value) };
+
+    let pm = raw.hour & HOUR_PM != 0;
+    let mut hour = convert(raw.hour & !HOUR_PM);
+
+    // 12 AM is midnight and 12 PM is noon
+    if status_b & HOUR_24_MODE == 0 {
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let year = convert(raw.year) as u16;
+    let century = convert(raw.century) as u16;
+
+    let year = if (19..=29).contains(&century) {
+        century * 100 + year
+    } else if year < 80 {
+        2000 + year
+    } else {
+        1900 + year
+    };
+
+    DateTime {
+        year,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+// Current wall clock time as kept by the CMOS
+pub fn now() -> DateTime {
+    interrupts::without_interrupts(|| {
+        // An update can still start between two registers, read until two passes agree
+        let mut raw = read_raw();
+        loop {
+            let again = read_raw();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+
+        decode(raw, read_register(REG_STATUS_B))
+    })
+}
+
+struct Date;
+
+impl Command for Date {
+    fn name(&self) -> &'static str { "date" }
+    fn usage(&self) -> &'static str { "date" }
+    fn help(&self) -> &'static str { "Show the current date and time of the hardware clock." }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let _ = writeln!(ctx.out, "{}", now());
+        Ok(())
+    }
+}
+
+pub fn register_commands() {
+    shell::register(&Date);
+}
+
+#[test_case]
+fn test_decode_bcd_12_hour() {
+    let raw = RawTime {
+        second: 0x59,
+        minute: 0x30,
+        hour: HOUR_PM | 0x12,
+        day: 0x31,
+        month: 0x12,
+        year: 0x26,
+        century: 0x20,
+    };
+    let time = decode(raw, 0);
+    assert_eq!(time, DateTime { year: 2026, month: 12, day: 31, hour: 12, minute: 30, second: 59 });
+
+    let raw = RawTime { hour: 0x12, ..raw };
+    assert_eq!(decode(raw, 0).hour, 0);
+}
+
+#[test_case]
+fn test_decode_binary_24_hour() {
+    let raw = RawTime {
+        second: 5,
+        minute: 7,
+        hour: 23,
+        day: 1,
+        month: 2,
+        year: 99,
+        century: 0,
+    };
+    let time = decode(raw, BINARY_MODE | HOUR_24_MODE);
+    assert_eq!(time, DateTime { year: 1999, month: 2, day: 1, hour: 23, minute: 7, second: 5 });
+}
@@ -1,198 +1,668 @@
-use crate::{fs::fat32::{FileSystem, BLOCK_DEVICE}, print};
+use crate::fs::vfs::{self, VFS};
 
 use x86_64::structures::paging::{OffsetPageTable, FrameAllocator, Size4KiB};
 
-use core::sync::atomic::Ordering::SeqCst;
-
 use spin::Mutex;
-use crate::AtomicBool;
 use lazy_static::lazy_static;
-use crate::debug;
-use debug::DEBUG_FS;
 use crate::println;
+use crate::vga_buffer::WRITER;
 
-pub static EXECUTE_COMMAND: AtomicBool = AtomicBool::new(false);
+// Most lines the history keeps, the oldest is dropped to make room
+pub const HISTORY_SIZE: usize = 32;
 
 use core::fmt::Write;
 
+use alloc::collections::VecDeque;
+use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 pub struct Terminal {
-    cwd: u32,
+    cwd: String,
     buffer: [u8; 512],
     index: usize,
+    // Position in `buffer` where typing goes, from 0 to `index`
+    cursor: usize,
+    history: VecDeque<String>,
+    // Number of the oldest line in `history`, lines keep their number as older ones are dropped
+    history_first: usize,
+    // Line of `history` shown by Up/Down, None while editing a new one
+    history_pos: Option<usize>,
+    // What was being typed before Up was pressed, given back by Down past the newest line
+    draft: Vec<u8>,
 }
 
 impl Terminal {
     pub fn new() -> Self {
         Self {
-            cwd: 2,
+            cwd: String::from("/"),
             buffer: [0;512],
             index: 0,
+            cursor: 0,
+            history: VecDeque::new(),
+            history_first: 1,
+            history_pos: None,
+            draft: Vec::new(),
         }
     }
 
-    pub fn push_char(&mut self, c: u8) -> String {
-        let mut out = String::new();
-        if self.index < self.buffer.len() {
-            self.buffer[self.index] = c;
-            self.index += 1;
+    // The line being edited
+    pub fn line(&self) -> &[u8] {
+        &self.buffer[..self.index]
+    }
+
+    fn set_line(&mut self, line: &[u8]) {
+        let len = line.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&line[..len]);
+        self.index = len;
+        self.cursor = len;
+    }
+
+    // Replaces the line with the one before it in the history. False if there is none, then
+    // nothing changes.
+    pub fn history_prev(&mut self) -> bool {
+        let pos = match self.history_pos {
+            Some(0) => return false,
+            Some(pos) => pos - 1,
+            None if self.history.is_empty() => return false,
+            None => {
+                self.draft = self.line().to_vec();
+                self.history.len() - 1
+            }
+        };
+
+        self.history_pos = Some(pos);
+        let line = self.history[pos].clone();
+        self.set_line(line.as_bytes());
+        true
+    }
+
+    // Replaces the line with the one after it in the history, or with the draft after the newest
+    // one. False if no history line is shown.
+    pub fn history_next(&mut self) -> bool {
+        match self.history_pos {
+            None => false,
+            Some(pos) if pos + 1 < self.history.len() => {
+                self.history_pos = Some(pos + 1);
+                let line = self.history[pos + 1].clone();
+                self.set_line(line.as_bytes());
+                true
+            }
+            Some(_) => {
+                self.history_pos = None;
+                let draft = core::mem::take(&mut self.draft);
+                self.set_line(&draft);
+                true
+            }
         }
-        let _ = write!(out, "{}", c as char);
-        out
     }
 
-    pub fn pop_char(&mut self){
-        if self.index > 0 {
-            self.index -= 1;
+    // What is printed before the line
+    pub fn prompt(&self) -> String {
+        format!("{}> ", self.cwd)
+    }
+
+    // Completes the word before the cursor, to a command if it is the first one on the line and
+    // to a name in the directory it names otherwise. It goes as far as all the names that match
+    // agree, and gives them back when there is more than one.
+    pub fn complete(&mut self) -> Vec<String> {
+        match self.start_completion() {
+            Some(completion) => {
+                let names = completion.candidates();
+                self.finish_completion(&completion, names)
+            }
+            None => Vec::new(),
         }
-        self.buffer[self.index] = b' ';
     }
 
-    pub fn execute_command(&mut self) -> String {
+    // The word `complete` works on, taken out so the names can be looked up without the terminal
+    pub fn start_completion(&self) -> Option<Completion> {
+        let before = core::str::from_utf8(&self.buffer[..self.cursor]).ok()?;
+        let word_start = before.rfind(' ').map_or(0, |idx| idx + 1);
 
-        let mut out = String::new();
+        let (name_start, dir) = if before[..word_start].trim().is_empty() {
+            (word_start, None)
+        } else {
+            let word = &before[word_start..];
+            let name_start = word_start + word.rfind('/').map_or(0, |idx| idx + 1);
+            (name_start, Some(vfs::normalize(&self.cwd, &before[word_start..name_start])))
+        };
+
+        Some(Completion {
+            line: self.line().to_vec(),
+            cursor: self.cursor,
+            name_start,
+            typed: String::from(&before[name_start..]),
+            dir,
+        })
+    }
 
-        let _ = writeln!(out,"");
+    // Puts in what the names from `completion.candidates` agree on. Nothing changes if the line
+    // was edited since `start_completion`.
+    pub fn finish_completion(&mut self, completion: &Completion, names: Vec<String>) -> Vec<String> {
+        if self.line() != completion.line.as_slice() || self.cursor != completion.cursor {
+            return Vec::new();
+        }
 
-        let input = &self.buffer[..self.index];
-        if let Ok(cmd) = core::str::from_utf8(input) {
+        // FAT names don't care about case, what is typed takes the case of the name
+        let typed = completion.typed.as_bytes();
+        let mut matches: Vec<String> = names.into_iter()
+            .filter(|name| {
+                name.len() >= typed.len() && name.as_bytes()[..typed.len()].eq_ignore_ascii_case(typed)
+            })
+            .collect();
+        matches.sort();
+
+        let first = match matches.first() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+        let mut common = first.len();
+        for name in &matches[1..] {
+            common = first.bytes().zip(name.bytes())
+                .take(common)
+                .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                .count();
+        }
+        while !first.is_char_boundary(common) {
+            common -= 1;
+        }
 
-            let trimmed = cmd.trim();
-            let mut parts = trimmed.split_whitespace();
+        let completed = String::from(&first[..common]);
+        self.replace_before_cursor(completion.name_start, completed.as_bytes());
 
-            let command = parts.next().unwrap_or("");
-            let arg = parts.next().unwrap_or("");
+        if matches.len() == 1 {
+            return Vec::new();
+        }
+        matches.iter().map(|name| String::from(name.trim_end_matches(' '))).collect()
+    }
 
-            match command {
+    // Replaces what is between `start` and the cursor with `text`, unless the line can't fit it
+    fn replace_before_cursor(&mut self, start: usize, text: &[u8]) {
+        let removed = self.cursor - start;
+        if self.index - removed + text.len() > self.buffer.len() {
+            return;
+        }
 
-                "ls" => {
-                    let mut dev = BLOCK_DEVICE.lock();
-                    let mut fs = FileSystem::new(&mut *dev)
-                        .expect("failed to mount FS");
-                    out.push_str(&fs.return_tree(self.cwd, 0));
-                }
-                "mkdir" => {
-                    let mut dev = BLOCK_DEVICE.lock();
-                    let mut fs = FileSystem::new(&mut *dev)
-                        .expect("failed to mount FS");
-                    if arg != "" {
-                        let _ = fs.create_dir(self.cwd, arg);
-                    }
-                }
-                "help" => {
-                    let _ = writeln!(out, "\
-Available commands:
+        self.buffer.copy_within(self.cursor..self.index, start + text.len());
+        self.buffer[start..start + text.len()].copy_from_slice(text);
+        self.index = self.index - removed + text.len();
+        self.cursor = start + text.len();
+    }
 
-ls
-List the contents of the current directory.
+    // Adds a line to the history, unless it is empty or the same as the last one
+    fn remember(&mut self, line: &str) {
+        if line.is_empty() || self.history.back().is_some_and(|last| last == line) {
+            return;
+        }
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+            self.history_first += 1;
+        }
+        self.history.push_back(String::from(line));
+    }
 
-mkdir <name>
-Create a new directory with the given <name> in the current directory.
+    // The lines in the history with their numbers, oldest first
+    pub fn history(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.history.iter()
+            .enumerate()
+            .map(move |(i, line)| (self.history_first + i, line.as_str()))
+    }
 
-touch <name>
-Create a new empty file with the given <name> in the current directory.
+    // Inserts a character at the cursor. The end of the line always goes at the end, wherever
+    // the cursor is.
+    pub fn push_char(&mut self, c: u8) {
+        if self.index == self.buffer.len() {
+            return;
+        }
+        if c == b'\n' || c == b'\r' {
+            self.cursor = self.index;
+        }
 
-cd <dirname>
-Change the current directory to <dirname>.
+        self.buffer.copy_within(self.cursor..self.index, self.cursor + 1);
+        self.buffer[self.cursor] = c;
+        self.index += 1;
+        self.cursor += 1;
+    }
 
-bk <component>
-Toggle FileSystem debugging on or off.
+    // Removes the character before the cursor
+    pub fn pop_char(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete_char();
+        }
+    }
 
-clear
-Clear the screen by printing empty lines.
+    // Removes the character at the cursor
+    pub fn delete_char(&mut self) {
+        if self.cursor < self.index {
+            self.buffer.copy_within(self.cursor + 1..self.index, self.cursor);
+            self.index -= 1;
+        }
+    }
 
-help
-Show this help message.");
-                }
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
 
-                "touch" => {
-                    let mut dev = BLOCK_DEVICE.lock();
-                    let mut fs = FileSystem::new(&mut *dev)
-                        .expect("failed to mount FS");
-                    if arg != "" {
-                        let _ = fs.create_file(self.cwd, arg);
-                    }
-                }
-                "cd" => {
-                    let mut dev = BLOCK_DEVICE.lock();
-                    let mut fs = FileSystem::new(&mut *dev)
-                        .expect("failed to mount FS");
-                    if arg != "" {
-
-                        if let Some(cluster) = fs.find_dir_in(self.cwd, arg) {
-                            self.cwd = cluster;
-                            let _ = writeln!(out, "Changed directory to {}", arg);
-                        } 
-
-                        else {
-                            let _ = writeln!(out, "Directory '{}' not found", arg);
-                        }
-
-                    } else {
-                        let _ = writeln!(out, "Usage: cd <dirname>");
-                    }
-                }
+    // Moves the cursor `delta` characters, staying inside the line. False if it didn't move.
+    pub fn move_cursor(&mut self, delta: isize) -> bool {
+        let cursor = self.cursor.saturating_add_signed(delta).min(self.index);
+        let moved = cursor != self.cursor;
+        self.cursor = cursor;
+        moved
+    }
 
-                "bk" => {
-
-                    match arg {
-                        "fs" => {
-                            let val = DEBUG_FS.load(SeqCst);
-                            DEBUG_FS.store(!val, SeqCst);
-
-                            if DEBUG_FS.load(SeqCst) {
-                                let _ = writeln!(out, "FileSystem debugging activated");
-                            }
-                            else{
-                                let _ = writeln!(out, "FileSystem debugging deactivated");
-                            }
-                        }
-                        "help" => {
-
-                    let _ = writeln!(out, "\
-Available components:
-
-fs
-File system.");
-                        }
-                        "" => {}
-                        _ => {}
-                    }
+    pub fn cursor_home(&mut self) -> bool {
+        self.move_cursor(-(self.index as isize))
+    }
 
-                    let _ = write!(out,"");
-                }
+    pub fn cursor_end(&mut self) -> bool {
+        self.move_cursor(self.index as isize)
+    }
+
+    // Takes the line out of the terminal to be run, and leaves an empty one for the next. The
+    // command gets its own copy of the current directory and the history.
+    pub fn take_line(&mut self) -> CommandLine {
+        self.history_pos = None;
+        self.draft.clear();
+
+        let line = core::str::from_utf8(self.line()).ok().map(|line| String::from(line.trim()));
+        if let Some(line) = &line {
+            self.remember(line);
+        }
+
+        self.index = 0;
+        self.cursor = 0;
+
+        CommandLine {
+            line,
+            cwd: self.cwd.clone(),
+            history: self.history.clone(),
+            history_first: self.history_first,
+        }
+    }
+
+    // Keeps what the command changed, which can only be the current directory
+    pub fn finish_line(&mut self, line: CommandLine) {
+        self.cwd = line.cwd;
+    }
+
+    // Runs the line and gives back what it printed, followed by the next prompt
+    pub fn execute_command(&mut self) -> String {
+        let mut line = self.take_line();
+        let mut out = line.run();
+        self.finish_line(line);
+
+        out.push_str(&self.prompt());
+        out
+    }
+}
+
+// A line taken out of the terminal to be run
+pub struct CommandLine {
+    // None if it isn't valid UTF-8
+    line: Option<String>,
+    cwd: String,
+    history: VecDeque<String>,
+    history_first: usize,
+}
+
+impl CommandLine {
+    // Runs the command on the line and gives back what it printed
+    pub fn run(&mut self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "");
 
-                "clear" => {
-                    for _ in 0..50 {
-                        let _ = writeln!(out,"");
+        let line = match &self.line {
+            Some(line) => line,
+            None => {
+                let _ = writeln!(out, "Invalid UTF-8 input");
+                return out;
+            }
+        };
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let Some((&name, args)) = words.split_first() {
+            match find_command(name) {
+                Some(command) => {
+                    let mut ctx = Context {
+                        cwd: &mut self.cwd,
+                        out: &mut out,
+                        history: &self.history,
+                        history_first: self.history_first,
+                    };
+                    if command.run(args, &mut ctx).is_err() {
+                        let _ = writeln!(out, "Usage: {}", command.usage());
                     }
                 }
-
-                "" => {}
-                _ => {
-                    let _ = writeln!(out, "Unknown command: {}", cmd);
+                None => {
+                    let _ = writeln!(out, "Unknown command: {}", name);
                 }
             }
+        }
+        out
+    }
+}
 
-        } else {
-            let _ = writeln!(out, "Invalid UTF-8 input");
+// A word being completed, with the line it was on
+pub struct Completion {
+    line: Vec<u8>,
+    cursor: usize,
+    // Where the name being completed starts in the line, after the directories of a path
+    name_start: usize,
+    typed: String,
+    // Directory the name is in, or None for a command
+    dir: Option<String>,
+}
+
+impl Completion {
+    // Every name the word could become, each with what goes after it once it is complete
+    pub fn candidates(&self) -> Vec<String> {
+        match &self.dir {
+            None => commands().iter().map(|command| format!("{} ", command.name())).collect(),
+            Some(dir) => {
+                let entries = VFS.lock().read_dir(dir).unwrap_or_default();
+                entries.into_iter()
+                    .map(|entry| format!("{}{}", entry.name, if entry.node.is_dir() { '/' } else { ' ' }))
+                    .collect()
+            }
         }
+    }
+}
 
-        let _ = write!(out, "> ");
+// A command the shell runs by name. The kernel and its modules add theirs with `register`.
+pub trait Command: Sync {
+    fn name(&self) -> &'static str;
+    // How it is called, like "ls [path]"
+    fn usage(&self) -> &'static str;
+    // What it does, for help
+    fn help(&self) -> &'static str;
+    // Runs it with the words that follow its name. On a wrong call the shell prints the usage.
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError>;
+}
 
-        self.index = 0;
-        out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageError;
+
+// What a command gets from the terminal that runs it
+pub struct Context<'a> {
+    // Directory relative paths start at, cd changes it
+    pub cwd: &'a mut String,
+    // What the command prints, shown once it is done
+    pub out: &'a mut String,
+    history: &'a VecDeque<String>,
+    history_first: usize,
+}
+
+impl Context<'_> {
+    // `path` as an absolute path, from the current directory if it is relative
+    pub fn path(&self, path: &str) -> String {
+        vfs::normalize(self.cwd, path)
+    }
+
+    // The lines in the history of the terminal with their numbers, oldest first
+    pub fn history(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.history.iter()
+            .enumerate()
+            .map(move |(i, line)| (self.history_first + i, line.as_str()))
+    }
+}
+
+lazy_static! {
+    static ref COMMANDS: Mutex<Vec<&'static dyn Command>> = Mutex::new(Vec::new());
+}
+
+// Adds a command to the shell, in place of an earlier one with the same name if there is one.
+// help lists them in the order they were added.
+pub fn register(command: &'static dyn Command) {
+    let mut commands = COMMANDS.lock();
+    match commands.iter_mut().find(|registered| registered.name() == command.name()) {
+        Some(registered) => *registered = command,
+        None => commands.push(command),
+    }
+}
+
+// Every registered command. The registry isn't locked while they run, so help can read it.
+pub fn commands() -> Vec<&'static dyn Command> {
+    COMMANDS.lock().clone()
+}
+
+fn find_command(name: &str) -> Option<&'static dyn Command> {
+    COMMANDS.lock().iter().copied().find(|command| command.name() == name)
+}
+
+// The commands of the shell itself, the rest come from the modules they belong to
+pub fn register_commands() {
+    for command in [&History as &'static dyn Command, &Clear, &Help] {
+        register(command);
     }
 }
 
+struct History;
+
+impl Command for History {
+    fn name(&self) -> &'static str { "history" }
+    fn usage(&self) -> &'static str { "history" }
+    fn help(&self) -> &'static str {
+        "List the last commands run, oldest first. Up and Down bring them back to the prompt."
+    }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let mut list = String::new();
+        for (number, line) in ctx.history() {
+            let _ = writeln!(list, "{:>5}  {}", number, line);
+        }
+        ctx.out.push_str(&list);
+        Ok(())
+    }
+}
+
+struct Clear;
+
+impl Command for Clear {
+    fn name(&self) -> &'static str { "clear" }
+    fn usage(&self) -> &'static str { "clear" }
+    fn help(&self) -> &'static str { "Clear the screen by printing empty lines." }
+
+    fn run(&self, _args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        for _ in 0..50 {
+            let _ = writeln!(ctx.out, "");
+        }
+        Ok(())
+    }
+}
+
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn usage(&self) -> &'static str { "help [command]" }
+    fn help(&self) -> &'static str {
+        "Show this help message, or only the one of [command]."
+    }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let commands = commands();
+
+        if let Some(name) = args.first() {
+            match commands.iter().find(|command| command.name() == *name) {
+                Some(command) => {
+                    let _ = writeln!(ctx.out, "{}\n{}", command.usage(), command.help());
+                }
+                None => {
+                    let _ = writeln!(ctx.out, "help: no command named {}", name);
+                }
+            }
+            return Ok(());
+        }
 
+        let _ = writeln!(ctx.out, "\
+Available commands:
+
+Paths can be absolute like /a/b or relative to the current directory like ../b. Tab completes command and file names.");
+
+        for command in commands {
+            let _ = write!(ctx.out, "\n{}\n{}\n", command.usage(), command.help());
+        }
+        Ok(())
+    }
+}
+
+// Only the shell loop takes it, the keyboard interrupt queues its keys with `queue_key`
 lazy_static! {
     pub static ref TERMINAL: Mutex<Terminal> = Mutex::new(Terminal::new());
 }
 
+// Draws the line being typed where it started, with its cursor. Without the cursor the line is
+// left as it is and the next one starts after it.
+pub fn draw_line(term: &Terminal, with_cursor: bool) {
+    let cursor = if with_cursor { Some(term.cursor()) } else { None };
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        WRITER.lock().draw_input(term.line(), cursor);
+    });
+}
+
+// Completes the word at the cursor of the terminal and, when several names match, lists them
+// and starts the line again below. The directory is read without holding the terminal.
+pub fn complete_line() {
+    let completion = match TERMINAL.lock().start_completion() {
+        Some(completion) => completion,
+        None => return,
+    };
+    let names = completion.candidates();
+
+    let mut term = TERMINAL.lock();
+    let matches = term.finish_completion(&completion, names);
+
+    if !matches.is_empty() {
+        draw_line(&term, false);
+        crate::print!("\n{}\n{}", matches.join("  "), term.prompt());
+    }
+    draw_line(&term, true);
+}
+
+// Runs the line entered in the terminal and prints what the command printed and the next
+// prompt. The terminal isn't held while the command runs, keys pressed meanwhile wait in the
+// queue and go to the next line.
+pub fn run_line() {
+    let mut line = {
+        let mut term = TERMINAL.lock();
+        draw_line(&term, false);
+        crate::print!("\n");
+        term.take_line()
+    };
+    let out = line.run();
+
+    let mut term = TERMINAL.lock();
+    term.finish_line(line);
+    crate::print!("{}{}", out, term.prompt());
+    draw_line(&term, true);
+}
+
+// A key for the terminal, queued by the keyboard interrupt for the shell loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+}
+
+// Keys the shell loop hasn't handled yet, more than this are dropped
+const KEY_QUEUE_SIZE: usize = 64;
+
+struct KeyQueue {
+    keys: [Key; KEY_QUEUE_SIZE],
+    first: usize,
+    len: usize,
+}
+
+impl KeyQueue {
+    const fn new() -> Self {
+        Self { keys: [Key::End; KEY_QUEUE_SIZE], first: 0, len: 0 }
+    }
+
+    fn push(&mut self, key: Key) {
+        if self.len < KEY_QUEUE_SIZE {
+            self.keys[(self.first + self.len) % KEY_QUEUE_SIZE] = key;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<Key> {
+        if self.len == 0 {
+            return None;
+        }
+        let key = self.keys[self.first];
+        self.first = (self.first + 1) % KEY_QUEUE_SIZE;
+        self.len -= 1;
+        Some(key)
+    }
+}
+
+// Taken with interrupts off outside the keyboard interrupt, so the interrupt never waits for it
+static KEYS: Mutex<KeyQueue> = Mutex::new(KeyQueue::new());
+
+// Called by the keyboard interrupt
+pub fn queue_key(key: Key) {
+    KEYS.lock().push(key);
+}
+
+// Handles the keys queued since the last call, in the order they were pressed
+pub fn handle_keys() {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    while let Some(key) = without_interrupts(|| KEYS.lock().pop()) {
+        match key {
+            Key::Enter => run_line(),
+            Key::Tab => complete_line(),
+            _ => edit_line(key),
+        }
+    }
+}
+
+// Edits the line or moves through it, and draws it again if that changed anything
+fn edit_line(key: Key) {
+    let mut term = TERMINAL.lock();
+    let changed = match key {
+        Key::Char(c) => {
+            term.push_char(c);
+            true
+        }
+        Key::Backspace => {
+            term.pop_char();
+            true
+        }
+        Key::Delete => {
+            term.delete_char();
+            true
+        }
+        Key::Up => term.history_prev(),
+        Key::Down => term.history_next(),
+        Key::Left => term.move_cursor(-1),
+        Key::Right => term.move_cursor(1),
+        Key::Home => term.cursor_home(),
+        Key::End => term.cursor_end(),
+        Key::Enter | Key::Tab => false,
+    };
+    if changed {
+        draw_line(&term, true);
+    }
+}
+
 pub fn init() {
     lazy_static::initialize(&TERMINAL);
+    register_commands();
 
-    crate::print!("> ");
+    let term = TERMINAL.lock();
+    crate::print!("{}", term.prompt());
+    draw_line(&term, true);
 }
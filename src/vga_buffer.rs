@@ -40,6 +40,12 @@ impl ColorCode {
     fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    // The same colors the other way round, for the cursor. A bright foreground would land on the
+    // blink bit, so that one is cleared.
+    fn inverted(self) -> ColorCode {
+        ColorCode(self.0.rotate_left(4) & 0x7F)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -49,8 +55,8 @@ struct ScreenChar {
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
 
 use volatile::Volatile;
 
@@ -60,7 +66,11 @@ struct Buffer {
 }
 
 pub struct Writer {
+    // Row written to, the last one unless an edited line got shorter
+    row: usize,
     column_position: usize,
+    // Row and column where the line being edited starts, moved up when the screen scrolls
+    input_start: Option<(usize, usize)>,
     color_code: ColorCode,
     buffer: &'static mut Buffer,
 }
@@ -74,7 +84,7 @@ impl Writer {
                     self.new_line();
                 }
 
-                let row = BUFFER_HEIGHT - 1;
+                let row = self.row;
                 let col = self.column_position;
 
                 let color_code = self.color_code;
@@ -102,7 +112,7 @@ impl Writer {
 
             self.column_position -= 1;
 
-            let row = BUFFER_HEIGHT - 1;
+            let row = self.row;
             let col = self.column_position;
 
             let blank = ScreenChar {
@@ -114,11 +124,81 @@ impl Writer {
         }
     }
 
+    pub fn column(&self) -> usize {
+        self.column_position
+    }
+
     pub fn cursor_position(&mut self, pos: usize){
+        self.row = BUFFER_HEIGHT - 1;
         self.column_position = pos;
     }
 
+    // Draws the line being edited from where it started, over what was drawn of it before, with
+    // the cursor on the character at `cursor`. It wraps at the end of a row and scrolls the screen
+    // when it doesn't fit. With no cursor the line is done and the next one starts elsewhere.
+    pub fn draw_input(&mut self, line: &[u8], cursor: Option<usize>) {
+        let (row, col) = *self.input_start.get_or_insert((self.row, self.column_position));
+        let mut start = row * BUFFER_WIDTH + col;
+
+        // The cell after the line has to fit too, for the cursor at the end
+        let cells = BUFFER_WIDTH * BUFFER_HEIGHT;
+        let mut len = line.len();
+        while start >= BUFFER_WIDTH && start + len >= cells {
+            self.row = BUFFER_HEIGHT - 1;
+            self.new_line();
+            start -= BUFFER_WIDTH;
+        }
+        len = len.min(cells - 1 - start);
+
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for cell in start..cells {
+            self.buffer.chars[cell / BUFFER_WIDTH][cell % BUFFER_WIDTH].write(blank);
+        }
+
+        for i in 0..=len {
+            let ascii_character = match line.get(i) {
+                Some(0x20..=0x7e) => line[i],
+                Some(_) => 0xfe,
+                None => b' ',
+            };
+            let color_code = if cursor == Some(i) { self.color_code.inverted() } else { self.color_code };
+
+            let cell = start + i;
+            self.buffer.chars[cell / BUFFER_WIDTH][cell % BUFFER_WIDTH].write(ScreenChar {
+                ascii_character,
+                color_code,
+            });
+        }
+
+        // Writing goes on after the line, at the end of its last row if it fills it
+        let end = start + len;
+        if len > 0 && end % BUFFER_WIDTH == 0 {
+            self.row = end / BUFFER_WIDTH - 1;
+            self.column_position = BUFFER_WIDTH;
+        } else {
+            self.row = end / BUFFER_WIDTH;
+            self.column_position = end % BUFFER_WIDTH;
+        }
+
+        if cursor.is_none() {
+            self.input_start = None;
+        }
+    }
+
     fn new_line(&mut self) {
+        if self.row < BUFFER_HEIGHT - 1 {
+            self.row += 1;
+            self.column_position = 0;
+            return;
+        }
+
+        if let Some((row, col)) = self.input_start {
+            self.input_start = Some(if row > 0 { (row - 1, col) } else { (0, 0) });
+        }
+
         for row in 1..BUFFER_HEIGHT {
             for col in 0..BUFFER_WIDTH {
                 let character = self.buffer.chars[row][col].read();
@@ -147,7 +227,9 @@ use lazy_static::lazy_static;
 
 lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
+        row: BUFFER_HEIGHT - 1,
         column_position: 0,
+        input_start: None,
         color_code: ColorCode::new(Color::Yellow, Color::Black),
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
     });
@@ -200,3 +282,33 @@ fn test_println_output() {
     });
 }
 
+#[test_case]
+fn test_draw_input_wraps() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let line = [b'x'; 100];
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        write!(writer, "\n> ").expect("write failed");
+        writer.draw_input(&line, Some(0));
+
+        // The line goes on from the prompt and takes the next row
+        let first = writer.buffer.chars[BUFFER_HEIGHT - 2][2].read();
+        assert_eq!(first.ascii_character, b'x');
+        assert_eq!(first.color_code, writer.color_code.inverted());
+        let last = writer.buffer.chars[BUFFER_HEIGHT - 1][21].read();
+        assert_eq!(last.ascii_character, b'x');
+        assert_eq!(last.color_code, writer.color_code);
+        assert_eq!(writer.buffer.chars[BUFFER_HEIGHT - 1][22].read().ascii_character, b' ');
+
+        // A shorter line leaves the last row empty and writing goes on after it
+        writer.draw_input(&line[..10], None);
+        assert_eq!(writer.buffer.chars[BUFFER_HEIGHT - 2][11].read().ascii_character, b'x');
+        assert_eq!(writer.buffer.chars[BUFFER_HEIGHT - 2][2].read().color_code, writer.color_code);
+        assert_eq!(writer.buffer.chars[BUFFER_HEIGHT - 1][0].read().ascii_character, b' ');
+        writeln!(writer).expect("writeln failed");
+        assert_eq!(writer.row, BUFFER_HEIGHT - 1);
+    });
+}
+
@@ -0,0 +1,937 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ups::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use ups::fs::cache::CachedDevice;
+use ups::fs::error::FsError;
+use ups::fs::disk::RAM_DISK_SECTORS;
+use ups::fs::fat32::{fat_date, BlockDevice, FatType, FileSystem, Problem, RamDisk};
+use ups::fs::file::OpenFlags;
+use ups::fs::vfs::VFS;
+use ups::fs::volume::FatVolume;
+use ups::fs::{mkfs, HOOKS};
+use ups::rtc;
+use ups::shell::Terminal;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use ups::allocator;
+    use ups::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    ups::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    ups::fs::commands::register_commands();
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    ups::test_panic_handler(info)
+}
+
+fn new_disk() -> RamDisk {
+    RamDisk::new(400, 8, 32, 2, 100, 2, 1, 6, 0x12345678, *b"NO NAME    ")
+}
+
+fn mount<D: BlockDevice>(disk: &mut D) -> FileSystem<'_, D> {
+    let mut fs = FileSystem::new(disk, HOOKS).expect("Failed to create FS");
+    fs.init_fats().unwrap();
+    fs.create_root_dir().unwrap();
+    fs
+}
+
+#[test_case]
+fn write_and_read_back() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_file(2, "hello.txt").unwrap();
+    fs.write_file(2, "hello.txt", b"Hello, world!").unwrap();
+
+    assert_eq!(fs.read_file(2, "hello.txt").unwrap(), b"Hello, world!");
+}
+
+#[test_case]
+fn write_spanning_several_clusters() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+    fs.create_file(2, "big.bin").unwrap();
+    fs.write_file(2, "big.bin", &data).unwrap();
+    assert_eq!(fs.read_file(2, "big.bin").unwrap(), data);
+
+    let occupied = fs.count_occupied_clusters().unwrap();
+    fs.write_file(2, "big.bin", b"short").unwrap();
+    assert_eq!(fs.read_file(2, "big.bin").unwrap(), b"short");
+    assert_eq!(fs.count_occupied_clusters().unwrap(), occupied - 2);
+}
+
+#[test_case]
+fn append_across_cluster_boundary() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_file(2, "log.txt").unwrap();
+    fs.write_file(2, "log.txt", &vec![b'a'; 4096]).unwrap();
+    fs.append(2, "log.txt", b"bc").unwrap();
+
+    let content = fs.read_file(2, "log.txt").unwrap();
+    assert_eq!(content.len(), 4098);
+    assert_eq!(&content[4095..], b"abc");
+}
+
+#[test_case]
+fn directory_grows_past_one_cluster() {
+    let mut disk = RamDisk::new(4000, 8, 32, 2, 100, 2, 1, 6, 0x12345678, *b"NO NAME    ");
+    let mut fs = mount(&mut disk);
+
+    fs.create_dir(2, "many").unwrap();
+    let dir = fs.find_dir_in(2, "MANY").unwrap();
+
+    // 128 entries fit in one cluster, "." and ".." included
+    for i in 0..200 {
+        fs.create_file(dir, &format!("F{}", i)).unwrap();
+    }
+
+    assert_eq!(fs.cluster_chain(dir).unwrap().len(), 2);
+    assert_eq!(fs.read_dir_entries(dir as usize).unwrap().len(), 202);
+
+    fs.write_file(dir, "F199", b"last").unwrap();
+    assert_eq!(fs.read_file(dir, "F199").unwrap(), b"last");
+}
+
+#[test_case]
+fn long_names_round_trip() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_file(2, "Hellowo.rld").unwrap();
+    fs.create_file(2, "a long file name.text").unwrap();
+    fs.create_file(2, "a long file name 2.text").unwrap();
+
+    let entries = fs.read_dir_entries(2).unwrap();
+    let find = |name: &str| entries.iter().find(|e| e.get_name().unwrap() == name).unwrap();
+
+    assert_eq!(find("Hellowo.rld").short_name().unwrap(), "HELLOWO.RLD");
+    assert_eq!(find("a long file name.text").short_name().unwrap(), "ALONGF~1.TEX");
+    assert_eq!(find("a long file name 2.text").short_name().unwrap(), "ALONGF~2.TEX");
+
+    // Lookups are case insensitive and accept the short alias too
+    fs.write_file(2, "A LONG FILE NAME 2.TEXT", b"data").unwrap();
+    assert_eq!(fs.read_file(2, "alongf~2.tex").unwrap(), b"data");
+
+    assert_eq!(fs.create_file(2, "hellowo.RLD"), Err(FsError::AlreadyExists));
+}
+
+#[test_case]
+fn errors_instead_of_panics() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_dir(2, "dir").unwrap();
+    fs.create_file(2, "file").unwrap();
+
+    assert_eq!(fs.find_dir_in(2, "missing"), Err(FsError::NotFound));
+    assert_eq!(fs.find_dir_in(2, "file"), Err(FsError::NotADirectory));
+    assert_eq!(fs.read_file(2, "dir"), Err(FsError::IsADirectory));
+    assert_eq!(fs.create_file(2, "bad:name"), Err(FsError::InvalidName));
+
+    // The small test disk only has 21 clusters, and each file takes one once it has content
+    let mut result = Ok(());
+    for i in 0..32 {
+        let name = format!("f{}", i);
+        result = fs.create_file(2, &name).and_then(|_| fs.write_file(2, &name, b"x"));
+        if result.is_err() {
+            break;
+        }
+    }
+    assert_eq!(result, Err(FsError::NoSpace));
+}
+
+// Fails every write from sector `from` on, like a disk that went bad there
+struct FailingWrites<'a> {
+    disk: &'a mut RamDisk,
+    from: u64,
+}
+
+impl BlockDevice for FailingWrites<'_> {
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; 512]) -> Result<(), FsError> {
+        self.disk.read_sector(lba, buf)
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; 512]) -> Result<(), FsError> {
+        if lba >= self.from {
+            return Err(FsError::Io);
+        }
+        self.disk.write_sector(lba, buf)
+    }
+}
+
+#[test_case]
+fn failed_create_dir_gives_its_cluster_back() {
+    let mut disk = new_disk();
+    let free = {
+        let mut fs = mount(&mut disk);
+        fs.free_clusters().unwrap()
+    };
+
+    // The FATs can still be written but the new cluster can't be emptied
+    let mut failing = FailingWrites { disk: &mut disk, from: 32 + 2 * 100 };
+    let mut fs = FileSystem::new(&mut failing, HOOKS).unwrap();
+    assert_eq!(fs.create_dir(2, "dir"), Err(FsError::Io));
+    assert_eq!(fs.free_clusters().unwrap(), free);
+    assert_eq!(fs.count_occupied_clusters().unwrap(), 3);
+    assert_eq!(fs.find_entry(2, "dir").err(), Some(FsError::NotFound));
+}
+
+#[test_case]
+fn free_count_survives_remount() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_dir(2, "dir").unwrap();
+    fs.create_file(2, "file").unwrap();
+    fs.write_file(2, "file", &vec![1u8; 9000]).unwrap();
+
+    // The occupied count includes the two reserved FAT entries
+    let total = fs.cluster_count() as usize + 2;
+    let free = fs.free_clusters().unwrap();
+    assert_eq!(free as usize, total - fs.count_occupied_clusters().unwrap());
+
+    fs.write_file(2, "file", b"small").unwrap();
+    let free = fs.free_clusters().unwrap();
+    assert_eq!(free as usize, total - fs.count_occupied_clusters().unwrap());
+
+    let mut fs = FileSystem::new(&mut disk, HOOKS).unwrap();
+    assert_eq!(fs.fs_info.unwrap().free_count, free);
+    assert_eq!(fs.free_clusters().unwrap(), free);
+}
+
+#[test_case]
+fn format_ram_disk() {
+    let mut disk = RamDisk::blank(RAM_DISK_SECTORS);
+    mkfs::format(&mut disk, RAM_DISK_SECTORS as u64, Some("ups"), HOOKS).unwrap();
+
+    let mut fs = FileSystem::new(&mut disk, HOOKS).unwrap();
+    assert_eq!(fs.bpb.sectors_per_cluster, 1);
+    assert_eq!(&fs.ebr.volume_label, b"UPS        ");
+    assert!(fs.cluster_count() >= 65525);
+    assert_eq!(fs.free_clusters().unwrap(), fs.cluster_count() - 1);
+    assert!(fs.read_dir_entries(2).unwrap().is_empty());
+
+    fs.create_file(2, "hello.txt").unwrap();
+    fs.write_file(2, "hello.txt", b"Hello").unwrap();
+    assert_eq!(fs.read_file(2, "hello.txt").unwrap(), b"Hello");
+}
+
+#[test_case]
+fn format_rejects_small_volumes() {
+    let mut disk = new_disk();
+    assert_eq!(mkfs::format(&mut disk, 400, None, HOOKS), Err(FsError::InvalidSize));
+}
+
+// Sets FAT entry `cluster` in the given copies of the FAT of `new_disk()`
+fn set_fat_entry(disk: &mut RamDisk, copies: &[u64], cluster: u32, value: u32) {
+    for &copy in copies {
+        let lba = 32 + copy * 100 + (cluster as u64 * 4) / 512;
+        let offset = (cluster as usize * 4) % 512;
+
+        let mut sector = [0u8; 512];
+        disk.read_sector(lba, &mut sector).unwrap();
+        sector[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        disk.write_sector(lba, &sector).unwrap();
+    }
+}
+
+#[test_case]
+fn check_finds_and_repairs_problems() {
+    let mut disk = new_disk();
+    {
+        let mut fs = mount(&mut disk);
+        fs.create_dir(2, "dir").unwrap();
+        fs.create_file(2, "file").unwrap();
+        fs.write_file(2, "file", &vec![1u8; 5000]).unwrap();
+        assert_eq!(fs.check(false).unwrap(), Vec::new());
+    }
+
+    // A lost cluster, and a second FAT that disagrees with the first one
+    set_fat_entry(&mut disk, &[0, 1], 20, 0x0FFF_FFFF);
+    set_fat_entry(&mut disk, &[1], 2, 0);
+
+    let mut fs = FileSystem::new(&mut disk, HOOKS).unwrap();
+    let problems = fs.check(false).unwrap();
+    assert!(problems.contains(&Problem::FatCopyMismatch { fat_idx: 1, entries: 1 }));
+    assert!(problems.contains(&Problem::LostClusters { count: 1 }));
+
+    assert!(!fs.check(true).unwrap().is_empty());
+    assert_eq!(fs.check(false).unwrap(), Vec::new());
+    assert_eq!(fs.read_file(2, "file").unwrap(), vec![1u8; 5000]);
+}
+
+#[test_case]
+fn remove_files_and_directories() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+    let free = fs.free_clusters().unwrap();
+
+    fs.create_file(2, "a long file name.txt").unwrap();
+    fs.write_file(2, "a long file name.txt", &vec![1u8; 5000]).unwrap();
+    fs.create_dir(2, "dir").unwrap();
+    let dir = fs.find_dir_in(2, "dir").unwrap();
+    fs.create_dir(dir, "sub").unwrap();
+    fs.create_file(dir, "file").unwrap();
+
+    assert_eq!(fs.remove(2, "dir"), Err(FsError::IsADirectory));
+    assert_eq!(fs.remove_dir(2, "a long file name.txt", false), Err(FsError::NotADirectory));
+    assert_eq!(fs.remove_dir(2, "dir", false), Err(FsError::NotEmpty));
+    assert_eq!(fs.remove_dir(dir, "..", true), Err(FsError::InvalidArgument));
+    assert_eq!(fs.remove(dir, "."), Err(FsError::InvalidArgument));
+
+    fs.remove(2, "a long file name.txt").unwrap();
+    assert_eq!(fs.read_file(2, "a long file name.txt"), Err(FsError::NotFound));
+
+    fs.remove_dir(2, "dir", true).unwrap();
+    assert_eq!(fs.find_dir_in(2, "dir"), Err(FsError::NotFound));
+
+    // Only "." and ".." are left in the root, and every cluster is back
+    assert_eq!(fs.read_dir_entries(2).unwrap().len(), 2);
+    assert_eq!(fs.free_clusters().unwrap(), free);
+    assert_eq!(fs.check(false).unwrap(), Vec::new());
+}
+
+#[test_case]
+fn rename_and_move() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_file(2, "notes.txt").unwrap();
+    fs.write_file(2, "notes.txt", b"some notes").unwrap();
+    fs.create_file(2, "other").unwrap();
+    fs.create_dir(2, "a").unwrap();
+    let a = fs.find_dir_in(2, "a").unwrap();
+    fs.create_dir(a, "b").unwrap();
+    let b = fs.find_dir_in(a, "b").unwrap();
+
+    fs.rename(2, "notes.txt", 2, "Meeting notes.txt").unwrap();
+    assert_eq!(fs.read_file(2, "meeting notes.txt").unwrap(), b"some notes");
+    assert_eq!(fs.read_file(2, "notes.txt"), Err(FsError::NotFound));
+
+    // Only the case changes
+    fs.rename(2, "other", 2, "OTHER").unwrap();
+    assert!(fs.read_dir_entries(2).unwrap().iter().any(|e| e.get_name().unwrap() == "OTHER"));
+
+    assert_eq!(fs.rename(2, "OTHER", 2, "a"), Err(FsError::AlreadyExists));
+    assert_eq!(fs.rename(2, "a", b, "a"), Err(FsError::InvalidArgument));
+    assert_eq!(fs.rename(2, "a", a, "a"), Err(FsError::InvalidArgument));
+
+    // Moving b to the root updates its "..", which stores 0 for the root
+    fs.rename(a, "b", 2, "b").unwrap();
+    assert_eq!(fs.find_dir_in(2, "b").unwrap(), b);
+    assert_eq!(fs.find_dir_in(b, "..").unwrap(), 0);
+    assert_eq!(fs.resolve_dir(b, "..").unwrap(), 2);
+
+    fs.rename(2, "Meeting notes.txt", b, "notes.txt").unwrap();
+    assert_eq!(fs.read_file(b, "notes.txt").unwrap(), b"some notes");
+
+    assert_eq!(fs.check(false).unwrap(), Vec::new());
+}
+
+#[test_case]
+fn entries_are_timestamped() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    let today = fat_date(&rtc::now());
+    assert!(rtc::now().year >= 2024);
+
+    fs.create_file(2, "stamped").unwrap();
+    fs.write_file(2, "stamped", b"data").unwrap();
+
+    let entries = fs.read_dir_entries(2).unwrap();
+    let entry = entries.iter().find(|e| e.get_name().unwrap() == "stamped").unwrap();
+
+    // Midnight could pass in between
+    assert!(entry.creation_date >= today);
+    assert!(entry.write_date >= entry.creation_date);
+    assert_eq!(entry.last_access_date, entry.write_date);
+}
+
+#[test_case]
+fn resolve_paths() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+
+    fs.create_dir(2, "a").unwrap();
+    let a = fs.find_dir_in(2, "a").unwrap();
+    fs.create_dir(a, "b").unwrap();
+    let b = fs.find_dir_in(a, "b").unwrap();
+
+    assert_eq!(fs.resolve_dir(2, "a/b").unwrap(), b);
+    assert_eq!(fs.resolve_dir(b, "/").unwrap(), 2);
+    assert_eq!(fs.resolve_dir(b, "..").unwrap(), a);
+    assert_eq!(fs.resolve_dir(b, "../..").unwrap(), 2);
+    assert_eq!(fs.resolve_dir(2, "..").unwrap(), 2);
+    assert_eq!(fs.resolve_dir(a, "./b/../b/").unwrap(), b);
+    assert_eq!(fs.resolve_dir(b, "/a//b").unwrap(), b);
+    assert_eq!(fs.resolve_dir(2, "a/missing"), Err(FsError::NotFound));
+
+    assert_eq!(fs.resolve(b, "/a/b/file.txt").unwrap(), (b, "file.txt"));
+    assert_eq!(fs.resolve(b, "../x/").unwrap(), (a, "x"));
+    assert_eq!(fs.resolve(a, "x").unwrap(), (a, "x"));
+    assert_eq!(fs.resolve(a, "/x").unwrap(), (2, "x"));
+    assert_eq!(fs.resolve(a, "/"), Err(FsError::InvalidArgument));
+
+    let (parent, name) = fs.resolve(b, "/a/new dir").unwrap();
+    fs.create_dir(parent, name).unwrap();
+    assert!(fs.resolve_dir(2, "/a/new dir").is_ok());
+}
+
+#[test_case]
+fn cache_writes_back_on_eviction_and_sync() {
+    let mut cache = CachedDevice::new(RamDisk::blank(16), 4);
+    let mut sector = [0u8; 512];
+
+    for lba in 0..5u64 {
+        cache.write_sector(lba, &[lba as u8 + 1; 512]).unwrap();
+    }
+
+    // Sector 0 was the least recently used one and had to make room
+    cache.inner_mut().read_sector(0, &mut sector).unwrap();
+    assert_eq!(sector, [1; 512]);
+    cache.inner_mut().read_sector(4, &mut sector).unwrap();
+    assert_eq!(sector, [0; 512]);
+    assert_eq!(cache.dirty_sectors(), 4);
+
+    cache.read_sector(4, &mut sector).unwrap();
+    assert_eq!(sector, [5; 512]);
+
+    cache.sync().unwrap();
+    assert_eq!(cache.dirty_sectors(), 0);
+    cache.inner_mut().read_sector(4, &mut sector).unwrap();
+    assert_eq!(sector, [5; 512]);
+
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 5);
+    assert_eq!(stats.writebacks, 5);
+}
+
+#[test_case]
+fn file_system_on_a_cache() {
+    let mut cache = CachedDevice::new(new_disk(), 32);
+    {
+        let mut fs = mount(&mut cache);
+        fs.create_file(2, "cached.txt").unwrap();
+        fs.write_file(2, "cached.txt", &vec![7u8; 6000]).unwrap();
+        assert_eq!(fs.read_file(2, "cached.txt").unwrap(), vec![7u8; 6000]);
+        fs.sync().unwrap();
+    }
+    assert!(cache.stats().hits > 0);
+
+    let mut fs = FileSystem::new(cache.inner_mut(), HOOKS).unwrap();
+    assert_eq!(fs.read_file(2, "cached.txt").unwrap(), vec![7u8; 6000]);
+    assert_eq!(fs.check(false).unwrap(), Vec::new());
+}
+
+#[test_case]
+fn ata_reads_the_boot_disk() {
+    use ups::ata::AtaDrive;
+
+    // QEMU boots from the primary master, it is only read so the image isn't damaged
+    let mut drive = AtaDrive::primary_master().expect("no boot disk on the primary master");
+    assert!(drive.sector_count() > 0);
+
+    let mut boot_sector = [0u8; 512];
+    drive.read_sector(0, &mut boot_sector).unwrap();
+    assert_eq!(&boot_sector[510..512], &[0x55, 0xAA]);
+
+    let last = drive.sector_count() - 1;
+    assert!(drive.read_sector(last, &mut boot_sector).is_ok());
+    assert_eq!(drive.read_sector(last + 1, &mut boot_sector), Err(FsError::Io));
+}
+
+#[test_case]
+fn mbr_partitions() {
+    use ups::fs::partition::{read_partition_table, Partition, PartitionType, Scheme};
+
+    // A small partition of an unknown type followed by a FAT32 one, big enough for FAT32
+    let first = (64u64, 960u64);
+    let second = (1024u64, RAM_DISK_SECTORS as u64 - 1024);
+
+    let mut disk = RamDisk::blank(RAM_DISK_SECTORS);
+    let mut mbr = [0u8; 512];
+    for (i, &(kind, (start, count))) in [(0x83u8, first), (0x0C, second)].iter().enumerate() {
+        let entry = &mut mbr[446 + i * 16..][..16];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&(start as u32).to_le_bytes());
+        entry[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+    }
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    disk.write_sector(0, &mbr).unwrap();
+
+    let table = read_partition_table(&mut disk, RAM_DISK_SECTORS as u64).unwrap().unwrap();
+    assert_eq!(table.scheme, Scheme::Mbr);
+    assert_eq!(table.partitions.len(), 2);
+    assert_eq!(table.partitions[0].kind, PartitionType::Mbr(0x83));
+
+    let fat = table.fat_partition().unwrap();
+    assert_eq!(fat.number, 2);
+    assert_eq!((fat.start_lba, fat.sector_count), second);
+
+    {
+        let mut partition = Partition::new(&mut disk, fat.start_lba, fat.sector_count);
+        mkfs::format(&mut partition, fat.sector_count, None, HOOKS).unwrap();
+
+        let mut fs = FileSystem::new(&mut partition, HOOKS).unwrap();
+        fs.create_file(2, "inside.txt").unwrap();
+        fs.write_file(2, "inside.txt", b"data").unwrap();
+        assert_eq!(fs.read_file(2, "inside.txt").unwrap(), b"data");
+    }
+
+    // The boot sector went to the start of the partition, and the table is still there
+    let mut sector = [0u8; 512];
+    disk.read_sector(second.0, &mut sector).unwrap();
+    assert_eq!(&sector[82..90], b"FAT32   ");
+    disk.read_sector(0, &mut sector).unwrap();
+    assert_eq!(sector, mbr);
+    assert!(read_partition_table(&mut disk, RAM_DISK_SECTORS as u64).unwrap().is_some());
+
+    // Nothing past the end of a partition can be reached through it
+    let mut partition = Partition::new(&mut disk, first.0, first.1);
+    assert_eq!(partition.read_sector(first.1, &mut sector), Err(FsError::Io));
+    assert_eq!(partition.write_sector(first.1, &sector), Err(FsError::Io));
+    assert!(partition.read_sector(first.1 - 1, &mut sector).is_ok());
+}
+
+#[test_case]
+fn unpartitioned_volume_has_no_table() {
+    use ups::fs::partition::read_partition_table;
+
+    let mut disk = RamDisk::blank(RAM_DISK_SECTORS);
+    assert_eq!(read_partition_table(&mut disk, RAM_DISK_SECTORS as u64), Ok(None));
+
+    mkfs::format(&mut disk, RAM_DISK_SECTORS as u64, None, HOOKS).unwrap();
+    assert_eq!(read_partition_table(&mut disk, RAM_DISK_SECTORS as u64), Ok(None));
+}
+
+// CRC-32 of zlib, which GPT uses for the header and the entry array
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// A GPT header at `header_lba` for the 32 sectors of entries at `entries_lba`
+fn gpt_header(header_lba: u64, other_lba: u64, entries_lba: u64, entries: &[u8]) -> [u8; 512] {
+    let last_usable = RAM_DISK_SECTORS as u64 - 34;
+
+    let mut header = [0u8; 512];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[24..32].copy_from_slice(&header_lba.to_le_bytes());
+    header[32..40].copy_from_slice(&other_lba.to_le_bytes());
+    header[40..48].copy_from_slice(&34u64.to_le_bytes());
+    header[48..56].copy_from_slice(&last_usable.to_le_bytes());
+    header[56..72].copy_from_slice(&[0x5A; 16]);
+    header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&crc32(entries).to_le_bytes());
+
+    let crc = crc32(&header[..92]);
+    header[16..20].copy_from_slice(&crc.to_le_bytes());
+    header
+}
+
+#[test_case]
+fn gpt_partitions() {
+    use ups::fs::partition::{read_partition_table, PartitionType, Scheme};
+
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    const LINUX: [u8; 16] = [
+        0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4,
+    ];
+    const BASIC_DATA: [u8; 16] = [
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+    ];
+
+    // A Linux partition and then a FAT32 one up to the last usable sector
+    let sectors = RAM_DISK_SECTORS as u64;
+    let fat = (1024u64, sectors - 34 - 1024 + 1);
+
+    let mut entries = vec![0u8; 128 * 128];
+    for (i, (kind, start, end, name)) in [(LINUX, 64u64, 1023u64, "linux"), (BASIC_DATA, fat.0, sectors - 34, "ups data")]
+        .into_iter()
+        .enumerate()
+    {
+        let entry = &mut entries[i * 128..][..128];
+        entry[0..16].copy_from_slice(&kind);
+        entry[16..32].copy_from_slice(&[i as u8 + 1; 16]);
+        entry[32..40].copy_from_slice(&start.to_le_bytes());
+        entry[40..48].copy_from_slice(&end.to_le_bytes());
+        for (j, unit) in name.encode_utf16().enumerate() {
+            entry[56 + j * 2..][..2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    let mut disk = RamDisk::blank(RAM_DISK_SECTORS);
+    let mut mbr = [0u8; 512];
+    mbr[446 + 4] = 0xEE;
+    mbr[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&(RAM_DISK_SECTORS as u32 - 1).to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    disk.write_sector(0, &mbr).unwrap();
+
+    // The primary header and entries at the start, the backup ones at the end
+    let backup_entries = sectors - 33;
+    disk.write_sector(1, &gpt_header(1, sectors - 1, 2, &entries)).unwrap();
+    disk.write_sector(sectors - 1, &gpt_header(sectors - 1, 1, backup_entries, &entries)).unwrap();
+    for (i, chunk) in entries.chunks_exact(512).enumerate() {
+        let chunk = chunk.try_into().unwrap();
+        disk.write_sector(2 + i as u64, chunk).unwrap();
+        disk.write_sector(backup_entries + i as u64, chunk).unwrap();
+    }
+
+    let table = read_partition_table(&mut disk, sectors).unwrap().unwrap();
+    assert_eq!(table.scheme, Scheme::Gpt);
+    assert_eq!(table.partitions.len(), 2);
+    assert_eq!(table.partitions[0].kind, PartitionType::Gpt(LINUX));
+    assert_eq!(table.partitions[0].name, "linux");
+
+    let part = table.fat_partition().unwrap();
+    assert_eq!(part.number, 2);
+    assert_eq!((part.start_lba, part.sector_count), fat);
+    assert_eq!(part.name, "ups data");
+
+    // A damaged primary header, or entries that don't match its CRC, leave the backup
+    let mut sector = [0u8; 512];
+    disk.read_sector(1, &mut sector).unwrap();
+    disk.write_sector(1, &[0u8; 512]).unwrap();
+    assert_eq!(read_partition_table(&mut disk, sectors).unwrap().unwrap(), table);
+    disk.write_sector(1, &sector).unwrap();
+
+    disk.read_sector(2, &mut sector).unwrap();
+    sector[100] ^= 0xFF;
+    disk.write_sector(2, &sector).unwrap();
+    assert_eq!(read_partition_table(&mut disk, sectors).unwrap().unwrap(), table);
+
+    // Without the backup there is nothing left to read
+    disk.write_sector(sectors - 1, &[0u8; 512]).unwrap();
+    assert_eq!(read_partition_table(&mut disk, sectors), Err(FsError::Corrupt));
+}
+
+#[test_case]
+fn damaged_gpt_is_corrupt() {
+    use ups::fs::partition::read_partition_table;
+
+    // A protective MBR without a GPT header behind it, not even a backup one
+    let mut disk = RamDisk::blank(RAM_DISK_SECTORS);
+    let mut mbr = [0u8; 512];
+    mbr[446 + 4] = 0xEE;
+    mbr[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&(RAM_DISK_SECTORS as u32 - 1).to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    disk.write_sector(0, &mbr).unwrap();
+
+    assert_eq!(read_partition_table(&mut disk, RAM_DISK_SECTORS as u64), Err(FsError::Corrupt));
+    assert_eq!(read_partition_table(&mut disk, 0), Err(FsError::Corrupt));
+}
+
+#[test_case]
+fn read_and_write_at_offsets() {
+    let mut disk = new_disk();
+    let mut fs = mount(&mut disk);
+    let cluster_size = fs.cluster_size();
+
+    fs.create_file(2, "data.bin").unwrap();
+    let (pos, _) = fs.find_entry(2, "data.bin").unwrap();
+
+    // Across a cluster boundary
+    let data: Vec<u8> = (0..cluster_size + 100).map(|i| (i % 251) as u8).collect();
+    assert_eq!(fs.write_at(pos, 0, &data).unwrap(), data.len());
+
+    let mut buf = vec![0u8; 200];
+    assert_eq!(fs.read_at(pos, cluster_size as u64 - 50, &mut buf).unwrap(), 150);
+    assert_eq!(&buf[..150], &data[cluster_size - 50..]);
+    assert_eq!(fs.read_at(pos, data.len() as u64, &mut buf).unwrap(), 0);
+
+    // Overwrite in the middle, then write past the end
+    fs.write_at(pos, 10, b"hello").unwrap();
+    let end = data.len() as u64 + 20;
+    fs.write_at(pos, end, b"tail").unwrap();
+
+    let content = fs.read_file(2, "data.bin").unwrap();
+    assert_eq!(content.len(), end as usize + 4);
+    assert_eq!(&content[10..15], b"hello");
+    assert!(content[data.len()..end as usize].iter().all(|&b| b == 0));
+    assert_eq!(&content[end as usize..], b"tail");
+
+    // Truncating frees the clusters past the new end
+    let free = fs.free_clusters().unwrap();
+    fs.truncate_at(pos, 5).unwrap();
+    assert_eq!(fs.read_file(2, "data.bin").unwrap(), &data[..5]);
+    assert_eq!(fs.free_clusters().unwrap(), free + 1);
+    assert_eq!(fs.truncate_at(pos, 6), Err(FsError::InvalidArgument));
+
+    fs.create_dir(2, "dir").unwrap();
+    let (dir_pos, _) = fs.find_entry(2, "dir").unwrap();
+    assert_eq!(fs.read_at(dir_pos, 0, &mut buf), Err(FsError::IsADirectory));
+
+    fs.remove(2, "data.bin").unwrap();
+    assert_eq!(fs.read_at(pos, 0, &mut buf), Err(FsError::NotFound));
+}
+
+// A FAT12 or FAT16 volume laid out like other tools do: one reserved sector, two FATs and a fixed root
+fn small_fat_disk(total_sectors: u16, fat_size: u16, root_entries: u16) -> RamDisk {
+    let mut disk = RamDisk::blank(total_sectors as usize);
+
+    let mut boot = [0u8; 512];
+    boot[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"MSWIN4.1");
+    boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+    boot[13] = 1;
+    boot[14..16].copy_from_slice(&1u16.to_le_bytes());
+    boot[16] = 2;
+    boot[17..19].copy_from_slice(&root_entries.to_le_bytes());
+    boot[19..21].copy_from_slice(&total_sectors.to_le_bytes());
+    boot[21] = 0xF0;
+    boot[22..24].copy_from_slice(&fat_size.to_le_bytes());
+    boot[38] = 0x29;
+    boot[43..54].copy_from_slice(b"SMALL      ");
+    boot[54..62].copy_from_slice(b"FAT     ");
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+    disk.write_sector(0, &boot).unwrap();
+
+    disk
+}
+
+#[test_case]
+fn fat_type_follows_the_cluster_count() {
+    assert_eq!(FatType::from_cluster_count(4084), FatType::Fat12);
+    assert_eq!(FatType::from_cluster_count(4085), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65524), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65525), FatType::Fat32);
+}
+
+#[test_case]
+fn floppy_fat12_volume() {
+    let mut disk = small_fat_disk(2880, 9, 224);
+    let mut fs = mount(&mut disk);
+
+    assert_eq!(fs.fat_type(), FatType::Fat12);
+    assert_eq!(fs.root_cluster(), 0);
+    assert_eq!(&fs.ebr.volume_label, b"SMALL      ");
+    assert_eq!(fs.cluster_count(), 2880 - 1 - 18 - 14);
+
+    // 400 clusters, so the chain goes through entry 341 which straddles two FAT sectors
+    let data: Vec<u8> = (0..400 * 512u32).map(|i| (i % 253) as u8).collect();
+    fs.create_file(0, "big.bin").unwrap();
+    fs.write_file(0, "big.bin", &data).unwrap();
+    assert_eq!(fs.read_file(0, "big.bin").unwrap(), data);
+
+    fs.create_dir(0, "docs").unwrap();
+    let docs = fs.resolve_dir(0, "/docs").unwrap();
+    fs.create_file(docs, "a long file name.txt").unwrap();
+    fs.write_file(docs, "a long file name.txt", b"nested").unwrap();
+    assert_eq!(fs.resolve_dir(docs, "..").unwrap(), 0);
+    assert_eq!(fs.read_file(docs, "a long file name.txt").unwrap(), b"nested");
+
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+
+    let free = fs.free_clusters().unwrap();
+    fs.remove(0, "big.bin").unwrap();
+    assert_eq!(fs.free_clusters().unwrap(), free + 400);
+
+    // The FAT12 entries must survive a remount as they were written
+    drop(fs);
+    let mut fs = FileSystem::new(&mut disk, HOOKS).unwrap();
+    assert_eq!(fs.read_file(docs, "a long file name.txt").unwrap(), b"nested");
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+}
+
+#[test_case]
+fn fixed_root_fills_up() {
+    let mut disk = small_fat_disk(2880, 9, 16);
+    let mut fs = mount(&mut disk);
+
+    for i in 0..16 {
+        fs.create_file(0, &format!("F{}.TXT", i)).unwrap();
+    }
+    assert_eq!(fs.create_file(0, "F16.TXT"), Err(FsError::DirectoryFull));
+
+    // Subdirectories still grow as usual
+    fs.remove(0, "F0.TXT").unwrap();
+    fs.create_dir(0, "SUB").unwrap();
+    let sub = fs.resolve_dir(0, "SUB").unwrap();
+    for i in 0..40 {
+        fs.create_file(sub, &format!("F{}.TXT", i)).unwrap();
+    }
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+}
+
+#[test_case]
+fn fat16_volume() {
+    let mut disk = small_fat_disk(20000, 79, 512);
+    let mut fs = mount(&mut disk);
+
+    assert_eq!(fs.fat_type(), FatType::Fat16);
+    assert_eq!(fs.root_cluster(), 0);
+
+    let free = fs.free_clusters().unwrap();
+    let data: Vec<u8> = (0..300_000u32).map(|i| (i % 241) as u8).collect();
+    fs.create_file(0, "data.bin").unwrap();
+    fs.write_file(0, "data.bin", &data).unwrap();
+    assert_eq!(fs.read_file(0, "data.bin").unwrap(), data);
+    assert_eq!(fs.check(false).unwrap(), vec![]);
+
+    fs.rename(0, "data.bin", 0, "moved.bin").unwrap();
+    fs.remove(0, "moved.bin").unwrap();
+    assert_eq!(fs.free_clusters().unwrap(), free);
+    assert_eq!(fs.count_occupied_clusters().unwrap(), 2);
+}
+
+// A fresh volume on /, for the tests that go through the shell commands
+fn mount_root() {
+    let mut disk = new_disk();
+    mount(&mut disk);
+
+    let mut vfs = VFS.lock();
+    if vfs.is_mount_point("/") {
+        vfs.unmount("/").unwrap();
+    }
+    vfs.mount("/", Box::new(FatVolume::new(disk).unwrap())).unwrap();
+}
+
+fn run(term: &mut Terminal, line: &str) -> String {
+    for byte in line.bytes() {
+        term.push_char(byte);
+    }
+    term.execute_command()
+}
+
+fn create_with(path: &str, data: &[u8]) {
+    let mut vfs = VFS.lock();
+    let fd = vfs.open(path, OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    assert_eq!(vfs.write(fd, data).unwrap(), data.len());
+    vfs.close(fd).unwrap();
+}
+
+#[test_case]
+fn cat_prints_a_written_file() {
+    mount_root();
+    let mut term = Terminal::new();
+
+    run(&mut term, "write notes.txt hello   there");
+    assert_eq!(run(&mut term, "cat notes.txt"), "\nhello there\n/> ");
+
+    // write replaces the file
+    run(&mut term, "write notes.txt bye");
+    assert_eq!(run(&mut term, "cat /notes.txt"), "\nbye\n/> ");
+}
+
+#[test_case]
+fn echo_appends_to_a_file() {
+    mount_root();
+    let mut term = Terminal::new();
+
+    run(&mut term, "echo first >> log.txt");
+    run(&mut term, "echo second line >> log.txt");
+    assert_eq!(run(&mut term, "cat log.txt"), "\nfirst\nsecond line\n/> ");
+
+    run(&mut term, "echo only > log.txt");
+    assert_eq!(run(&mut term, "cat log.txt"), "\nonly\n/> ");
+
+    // The clusters are 4096 bytes, the line starts in the first one and ends in the second
+    let data = vec![b'x'; 4090];
+    create_with("/big.txt", &data);
+    run(&mut term, "echo across the boundary >> big.txt");
+
+    let expected = format!("\n{}across the boundary\n/> ", String::from_utf8(data).unwrap());
+    assert_eq!(run(&mut term, "cat big.txt"), expected);
+}
+
+#[test_case]
+fn hexdump_shows_binary_bytes() {
+    mount_root();
+    let mut term = Terminal::new();
+
+    create_with("/data.bin", &[
+        0x48, 0x69, 0x00, 0x7f, 0xff, 0x0a, 0x20, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+        0x01, 0x02,
+    ]);
+    assert_eq!(
+        run(&mut term, "hexdump data.bin"),
+        "\n\
+00000000  48 69 00 7f ff 0a 20 41  42 43 44 45 46 47 48 49  |Hi.... ABCDEFGHI|\n\
+00000010  01 02                                             |..|\n\
+00000012\n\
+/> "
+    );
+
+    create_with("/empty.bin", b"");
+    assert_eq!(run(&mut term, "hexdump empty.bin"), "\n00000000\n/> ");
+}
+
+#[test_case]
+fn file_commands_report_missing_files_and_directories() {
+    mount_root();
+    let mut term = Terminal::new();
+    run(&mut term, "mkdir docs");
+
+    assert_eq!(run(&mut term, "cat missing.txt"), "\ncat: missing.txt: No such file or directory\n/> ");
+    assert_eq!(run(&mut term, "cat docs"), "\ncat: docs: Is a directory\n/> ");
+
+    assert_eq!(run(&mut term, "hexdump missing.bin"), "\nhexdump: missing.bin: No such file or directory\n/> ");
+    assert_eq!(run(&mut term, "hexdump docs"), "\nhexdump: docs: Is a directory\n/> ");
+
+    // Writing creates the file, but not the directory it goes in
+    assert_eq!(run(&mut term, "write nowhere/a.txt hi"), "\nwrite: nowhere/a.txt: No such file or directory\n/> ");
+    assert_eq!(run(&mut term, "write docs hi"), "\nwrite: docs: Is a directory\n/> ");
+
+    assert_eq!(run(&mut term, "echo hi >> nowhere/a.txt"), "\necho: nowhere/a.txt: No such file or directory\n/> ");
+    assert_eq!(run(&mut term, "echo hi >> docs"), "\necho: docs: Is a directory\n/> ");
+    assert_eq!(run(&mut term, "echo hi > docs"), "\necho: docs: Is a directory\n/> ");
+
+    // Nothing was left open or created on the way
+    let mut vfs = VFS.lock();
+    assert!(vfs.open_files().is_empty());
+    assert_eq!(vfs.read_dir("/docs").unwrap().len(), 0);
+    assert_eq!(vfs.stat("/nowhere"), Err(FsError::NotFound));
+}
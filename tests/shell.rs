@@ -0,0 +1,359 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ups::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use ups::fs::fat32::{FileSystem, RamDisk};
+use ups::fs::file::OpenFlags;
+use ups::fs::vfs::{NodeKind, VFS};
+use ups::fs::volume::FatVolume;
+use ups::fs::HOOKS;
+use ups::shell::{self, Command, Context, Terminal, UsageError, HISTORY_SIZE};
+
+use core::fmt::Write;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use ups::allocator;
+    use ups::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    ups::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    ups::fs::commands::register_commands();
+    ups::shell::register_commands();
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    ups::test_panic_handler(info)
+}
+
+fn mount_root() {
+    let mut disk = RamDisk::new(400, 8, 32, 2, 100, 2, 1, 6, 0x12345678, *b"NO NAME    ");
+    {
+        let mut fs = FileSystem::new(&mut disk, HOOKS).expect("Failed to create FS");
+        fs.init_fats().unwrap();
+        fs.create_root_dir().unwrap();
+    }
+
+    let mut vfs = VFS.lock();
+    if !vfs.is_mount_point("/") {
+        vfs.mount("/", Box::new(FatVolume::new(disk).unwrap())).unwrap();
+    }
+}
+
+fn run(term: &mut Terminal, line: &str) -> String {
+    for byte in line.bytes() {
+        term.push_char(byte);
+    }
+    term.push_char(b'\n');
+    term.execute_command()
+}
+
+fn type_line(term: &mut Terminal, line: &str) {
+    for byte in line.bytes() {
+        term.push_char(byte);
+    }
+}
+
+fn history(term: &Terminal) -> Vec<(usize, String)> {
+    term.history().map(|(number, line)| (number, String::from(line))).collect()
+}
+
+#[test_case]
+fn history_keeps_the_lines_run() {
+    let mut term = Terminal::new();
+    run(&mut term, "echo one");
+    run(&mut term, "   ");
+    run(&mut term, "  echo two ");
+    run(&mut term, "echo two");
+
+    // Blank lines and repeats of the last one aren't kept, and the spaces around are trimmed
+    assert_eq!(history(&term), [(1, String::from("echo one")), (2, String::from("echo two"))]);
+
+    let out = run(&mut term, "history");
+    assert!(out.contains("    1  echo one\n"));
+    assert!(out.contains("    3  history\n"));
+}
+
+#[test_case]
+fn up_and_down_walk_the_history() {
+    let mut term = Terminal::new();
+    assert!(!term.history_prev());
+    assert!(!term.history_next());
+
+    run(&mut term, "echo one");
+    run(&mut term, "echo two");
+    type_line(&mut term, "ec");
+
+    assert!(term.history_prev());
+    assert_eq!(term.line(), b"echo two");
+    assert!(term.history_prev());
+    assert_eq!(term.line(), b"echo one");
+    assert!(!term.history_prev());
+    assert_eq!(term.line(), b"echo one");
+
+    assert!(term.history_next());
+    assert_eq!(term.line(), b"echo two");
+    // Past the newest line the draft comes back
+    assert!(term.history_next());
+    assert_eq!(term.line(), b"ec");
+    assert!(!term.history_next());
+}
+
+#[test_case]
+fn recalled_line_can_be_edited_and_run() {
+    let mut term = Terminal::new();
+    run(&mut term, "echo one");
+
+    term.history_prev();
+    term.pop_char();
+    type_line(&mut term, "ce");
+    term.push_char(b'\n');
+    let out = term.execute_command();
+
+    assert!(out.contains("once\n"));
+    assert_eq!(history(&term).last().unwrap().1, "echo once");
+    // Up starts again from the newest line
+    assert!(term.history_prev());
+    assert_eq!(term.line(), b"echo once");
+}
+
+#[test_case]
+fn history_drops_the_oldest_lines() {
+    let mut term = Terminal::new();
+    for i in 0..HISTORY_SIZE + 3 {
+        run(&mut term, &alloc::format!("echo {}", i));
+    }
+
+    let lines = history(&term);
+    assert_eq!(lines.len(), HISTORY_SIZE);
+    assert_eq!(lines[0], (4, String::from("echo 3")));
+    assert_eq!(lines.last().unwrap().0, HISTORY_SIZE + 3);
+}
+
+#[test_case]
+fn typing_goes_in_at_the_cursor() {
+    let mut term = Terminal::new();
+    type_line(&mut term, "exchoo");
+    assert_eq!(term.cursor(), 6);
+
+    assert!(term.move_cursor(-4));
+    term.pop_char();
+    assert!(term.move_cursor(3));
+    term.delete_char();
+    assert_eq!(term.line(), b"echo");
+    assert_eq!(term.cursor(), 4);
+
+    assert!(term.cursor_home());
+    assert!(!term.move_cursor(-1));
+    term.delete_char();
+    type_line(&mut term, "  ");
+    assert!(term.cursor_end());
+    assert!(!term.move_cursor(1));
+    type_line(&mut term, " hi");
+    assert_eq!(term.line(), b"  cho hi");
+
+    // Enter runs the whole line, wherever the cursor is
+    term.cursor_home();
+    term.delete_char();
+    term.move_cursor(1);
+    term.push_char(b'e');
+    term.move_cursor(3);
+    term.push_char(b'\n');
+    assert_eq!(term.line(), b" echo hi\n");
+    assert!(term.execute_command().contains("\nhi\n"));
+    assert_eq!(term.cursor(), 0);
+}
+
+#[test_case]
+fn full_line_takes_no_more() {
+    let mut term = Terminal::new();
+    for _ in 0..600 {
+        term.push_char(b'a');
+    }
+    assert_eq!(term.line().len(), 512);
+
+    term.cursor_home();
+    term.push_char(b'b');
+    assert_eq!(term.line()[0], b'a');
+    assert_eq!(term.cursor(), 0);
+}
+
+#[test_case]
+fn tab_completes_commands() {
+    let mut term = Terminal::new();
+    type_line(&mut term, "his");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"history ");
+
+    // Several match, the line goes as far as they agree and they are listed
+    let mut term = Terminal::new();
+    type_line(&mut term, "  m");
+    assert_eq!(term.complete(), ["mkdir", "mkfs", "mount", "mv"]);
+    assert_eq!(term.line(), b"  m");
+    type_line(&mut term, "K");
+    assert_eq!(term.complete(), ["mkdir", "mkfs"]);
+    assert_eq!(term.line(), b"  mk");
+
+    // A line edited while the names were looked up is left alone
+    let mut term = Terminal::new();
+    type_line(&mut term, "hist");
+    let completion = term.start_completion().unwrap();
+    let names = completion.candidates();
+    term.pop_char();
+    assert!(term.finish_completion(&completion, names).is_empty());
+    assert_eq!(term.line(), b"his");
+
+    let mut term = Terminal::new();
+    type_line(&mut term, "xyz");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"xyz");
+}
+
+#[test_case]
+fn tab_completes_paths() {
+    mount_root();
+    {
+        let mut vfs = VFS.lock();
+        vfs.create("/HELLODIR", NodeKind::Directory).unwrap();
+        vfs.create("/HELLODIR/NOTES.TXT", NodeKind::File).unwrap();
+        vfs.create("/HELLO.TXT", NodeKind::File).unwrap();
+    }
+
+    let mut term = Terminal::new();
+    type_line(&mut term, "cat hel");
+    assert_eq!(term.complete(), ["HELLO.TXT", "HELLODIR/"]);
+    assert_eq!(term.line(), b"cat HELLO");
+
+    type_line(&mut term, "d");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"cat HELLODIR/");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"cat HELLODIR/NOTES.TXT ");
+
+    // Only the word before the cursor is completed, what follows it stays
+    let mut term = Terminal::new();
+    type_line(&mut term, "mv /HELLODIR/../hello.t x");
+    term.move_cursor(-2);
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"mv /HELLODIR/../HELLO.TXT  x");
+    assert_eq!(term.cursor(), 26);
+
+    let mut term = Terminal::new();
+    type_line(&mut term, "ls /NOWHERE/a");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"ls /NOWHERE/a");
+}
+
+#[test_case]
+fn prompt_follows_the_current_directory() {
+    mount_root();
+    VFS.lock().create("/LongProjects", NodeKind::Directory).unwrap();
+    VFS.lock().create("/LongProjects/SRC", NodeKind::Directory).unwrap();
+
+    let mut term = Terminal::new();
+    assert_eq!(term.prompt(), "/> ");
+
+    // The path keeps the names as they are on disk
+    assert!(run(&mut term, "cd longprojects/src").ends_with("\n/LongProjects/SRC> "));
+    assert_eq!(run(&mut term, "pwd"), "\n/LongProjects/SRC\n/LongProjects/SRC> ");
+
+    assert!(run(&mut term, "cd ..").ends_with("\n/LongProjects> "));
+    assert!(run(&mut term, "cd nowhere").ends_with("\n/LongProjects> "));
+    assert!(run(&mut term, "cd /LONGPR~1/../").ends_with("\n/> "));
+    assert!(run(&mut term, "cd /LONGPR~1").ends_with("\n/LongProjects> "));
+}
+
+struct Greet;
+
+impl Command for Greet {
+    fn name(&self) -> &'static str { "greet" }
+    fn usage(&self) -> &'static str { "greet <name>" }
+    fn help(&self) -> &'static str { "Say hello to <name>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let name = args.first().ok_or(UsageError)?;
+        let _ = writeln!(ctx.out, "hello {} from {}", name, ctx.cwd);
+        Ok(())
+    }
+}
+
+struct LoudGreet;
+
+impl Command for LoudGreet {
+    fn name(&self) -> &'static str { "greet" }
+    fn usage(&self) -> &'static str { "greet <name>" }
+    fn help(&self) -> &'static str { "Shout hello to <name>." }
+
+    fn run(&self, args: &[&str], ctx: &mut Context) -> Result<(), UsageError> {
+        let _ = writeln!(ctx.out, "HELLO {}", args.join(" "));
+        Ok(())
+    }
+}
+
+#[test_case]
+fn registered_commands_run_and_get_help() {
+    let mut term = Terminal::new();
+    assert_eq!(run(&mut term, "greet"), "\nUnknown command: greet\n/> ");
+
+    shell::register(&Greet);
+    assert_eq!(run(&mut term, "greet  you"), "\nhello you from /\n/> ");
+    assert_eq!(run(&mut term, "greet"), "\nUsage: greet <name>\n/> ");
+
+    // help comes from the registry, in the order commands were added
+    let help = run(&mut term, "help");
+    assert!(help.contains("\nls [path]\nList the contents"));
+    assert!(help.ends_with("\ngreet <name>\nSay hello to <name>.\n/> "));
+    assert!(help.find("\nls [path]").unwrap() < help.find("\nhistory\n").unwrap());
+    assert_eq!(run(&mut term, "help greet"), "\ngreet <name>\nSay hello to <name>.\n/> ");
+
+    type_line(&mut term, "gre");
+    assert!(term.complete().is_empty());
+    assert_eq!(term.line(), b"greet ");
+    let mut term = Terminal::new();
+
+    // One with the same name takes its place
+    let count = shell::commands().len();
+    shell::register(&LoudGreet);
+    assert_eq!(shell::commands().len(), count);
+    assert_eq!(run(&mut term, "greet a b"), "\nHELLO a b\n/> ");
+}
+
+#[test_case]
+fn fsck_repairs_only_with_nothing_open() {
+    mount_root();
+    let mut term = Terminal::new();
+
+    assert_eq!(run(&mut term, "fsck -r now"), "\nUsage: fsck [-r]\n/> ");
+    assert_eq!(run(&mut term, "fsck -x"), "\nUsage: fsck [-r]\n/> ");
+
+    run(&mut term, "touch open.txt");
+    let fd = VFS.lock().open("/open.txt", OpenFlags::READ).unwrap();
+    assert_eq!(run(&mut term, "fsck -r"), "\nfsck: Device or resource busy, close the open files first\n/> ");
+    assert!(VFS.lock().is_mount_point("/"));
+    VFS.lock().close(fd).unwrap();
+    run(&mut term, "rm open.txt");
+}
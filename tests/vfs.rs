@@ -0,0 +1,249 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ups::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use ups::fs::error::FsError;
+use ups::fs::fat32::{FileSystem, RamDisk};
+use ups::fs::HOOKS;
+use ups::fs::file::{OpenFlags, SeekFrom, MAX_OPEN_FILES};
+use ups::fs::vfs::{normalize, split_parent, NodeKind, Vfs};
+use ups::fs::volume::FatVolume;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use ups::allocator;
+    use ups::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    ups::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    ups::test_panic_handler(info)
+}
+
+fn fat_volume() -> FatVolume<RamDisk> {
+    let mut disk = RamDisk::new(400, 8, 32, 2, 100, 2, 1, 6, 0x12345678, *b"NO NAME    ");
+    {
+        let mut fs = FileSystem::new(&mut disk, HOOKS).expect("Failed to create FS");
+        fs.init_fats().unwrap();
+        fs.create_root_dir().unwrap();
+    }
+    FatVolume::new(disk).unwrap()
+}
+
+fn names(vfs: &mut Vfs, path: &str) -> Vec<String> {
+    vfs.read_dir(path).unwrap().into_iter().map(|entry| entry.name).collect()
+}
+
+#[test_case]
+fn normalize_paths() {
+    assert_eq!(normalize("/", ""), "/");
+    assert_eq!(normalize("/a/b", ""), "/a/b");
+    assert_eq!(normalize("/a/b", "c"), "/a/b/c");
+    assert_eq!(normalize("/a/b", "../c/./d/"), "/a/c/d");
+    assert_eq!(normalize("/a/b", "/x//y"), "/x/y");
+    assert_eq!(normalize("/a", "../../.."), "/");
+
+    assert_eq!(split_parent("/a/b"), Ok(("/a", "b")));
+    assert_eq!(split_parent("/a"), Ok(("/", "a")));
+    assert_eq!(split_parent("/"), Err(FsError::InvalidArgument));
+}
+
+#[test_case]
+fn paths_on_one_volume() {
+    let mut vfs = Vfs::new();
+    assert_eq!(vfs.stat("/").err(), Some(FsError::NotFound));
+
+    vfs.mount("/", Box::new(fat_volume())).unwrap();
+    assert!(vfs.stat("/").unwrap().is_dir());
+
+    vfs.create("/docs", NodeKind::Directory).unwrap();
+    vfs.create("/docs/notes", NodeKind::Directory).unwrap();
+    let file = vfs.create("/docs/notes/todo.txt", NodeKind::File).unwrap();
+    assert_eq!(file.kind, NodeKind::File);
+    assert_eq!(vfs.stat("/docs/notes/../notes/todo.txt"), Ok(file));
+
+    assert_eq!(vfs.create("/docs", NodeKind::Directory).err(), Some(FsError::AlreadyExists));
+    assert_eq!(vfs.create("/missing/a", NodeKind::File).err(), Some(FsError::NotFound));
+    assert_eq!(vfs.read_dir("/docs/notes/todo.txt").err(), Some(FsError::NotADirectory));
+    assert_eq!(vfs.stat("/docs/notes/todo.txt/x").err(), Some(FsError::NotADirectory));
+
+    // "." and ".." are never listed
+    assert_eq!(names(&mut vfs, "/docs"), ["notes"]);
+
+    vfs.rename("/docs/notes/todo.txt", "/done.txt").unwrap();
+    assert_eq!(vfs.stat("/docs/notes/todo.txt").err(), Some(FsError::NotFound));
+    assert_eq!(vfs.stat("/done.txt").unwrap().kind, NodeKind::File);
+
+    vfs.create("/docs/notes/more.txt", NodeKind::File).unwrap();
+    assert_eq!(vfs.remove("/docs", false).err(), Some(FsError::NotEmpty));
+    vfs.remove("/docs", true).unwrap();
+    vfs.remove("/done.txt", false).unwrap();
+    assert!(names(&mut vfs, "/").is_empty());
+}
+
+#[test_case]
+fn two_mounted_volumes() {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Box::new(fat_volume())).unwrap();
+
+    vfs.create("/mnt", NodeKind::Directory).unwrap();
+    vfs.create("/root.txt", NodeKind::File).unwrap();
+
+    assert_eq!(vfs.mount("/root.txt", Box::new(fat_volume())).err(), Some(FsError::NotADirectory));
+    assert_eq!(vfs.mount("/nowhere", Box::new(fat_volume())).err(), Some(FsError::NotFound));
+    vfs.mount("/mnt", Box::new(fat_volume())).unwrap();
+    assert_eq!(vfs.mount("/mnt/", Box::new(fat_volume())).err(), Some(FsError::Busy));
+
+    // Paths below /mnt go to the second volume
+    vfs.create("/mnt/inner.txt", NodeKind::File).unwrap();
+    vfs.create("/mnt/sub", NodeKind::Directory).unwrap();
+    assert_eq!(names(&mut vfs, "/mnt"), ["inner.txt", "sub"]);
+    assert_eq!(names(&mut vfs, "/"), ["mnt", "root.txt"]);
+    assert_eq!(vfs.stat("/mnt/sub/../../root.txt").unwrap().kind, NodeKind::File);
+
+    assert_eq!(vfs.rename("/root.txt", "/mnt/root.txt").err(), Some(FsError::CrossDevice));
+    assert_eq!(vfs.rename("/mnt", "/other").err(), Some(FsError::Busy));
+    assert_eq!(vfs.remove("/mnt", true).err(), Some(FsError::Busy));
+    assert_eq!(vfs.unmount("/").err(), Some(FsError::Busy));
+
+    let mounts = vfs.mounts();
+    assert_eq!(mounts.len(), 2);
+    assert!(mounts.iter().all(|(_, fs_type)| *fs_type == "fat32"));
+
+    // Once unmounted the directory underneath shows again, and the volume keeps its files
+    let volume = vfs.unmount("/mnt").unwrap();
+    assert!(names(&mut vfs, "/mnt").is_empty());
+
+    vfs.create("/other", NodeKind::Directory).unwrap();
+    vfs.mount("/other", volume).unwrap();
+    assert_eq!(names(&mut vfs, "/other"), ["inner.txt", "sub"]);
+
+    let stats = vfs.stats("/other").unwrap();
+    assert!(stats.free_blocks < stats.total_blocks);
+    vfs.sync().unwrap();
+}
+
+#[test_case]
+fn descriptors() {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Box::new(fat_volume())).unwrap();
+
+    assert_eq!(vfs.open("/log.txt", OpenFlags::READ).err(), Some(FsError::NotFound));
+
+    let fd = vfs.open("/log.txt", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    assert_eq!(vfs.write(fd, b"hello ").unwrap(), 6);
+    assert_eq!(vfs.write(fd, b"world").unwrap(), 5);
+    assert_eq!(vfs.read(fd, &mut [0u8; 4]).err(), Some(FsError::BadDescriptor));
+    vfs.close(fd).unwrap();
+    assert_eq!(vfs.close(fd).err(), Some(FsError::BadDescriptor));
+    assert_eq!(vfs.stat("/log.txt").unwrap().size, 11);
+
+    // Every descriptor has its own offset
+    let a = vfs.open("/log.txt", OpenFlags::READ).unwrap();
+    let b = vfs.open("/log.txt", OpenFlags::READ | OpenFlags::WRITE).unwrap();
+    assert_ne!(a, b);
+
+    let mut buf = [0u8; 5];
+    assert_eq!(vfs.read(a, &mut buf).unwrap(), 5);
+    assert_eq!(&buf, b"hello");
+
+    assert_eq!(vfs.seek(b, SeekFrom::End(-5)).unwrap(), 6);
+    vfs.write(b, b"WORLD").unwrap();
+    assert_eq!(vfs.seek(b, SeekFrom::Current(-11)).unwrap(), 0);
+    assert_eq!(vfs.seek(b, SeekFrom::Current(-1)).err(), Some(FsError::InvalidArgument));
+
+    let mut rest = [0u8; 16];
+    assert_eq!(vfs.read(a, &mut rest).unwrap(), 6);
+    assert_eq!(&rest[..6], b" WORLD");
+    assert_eq!(vfs.read(a, &mut rest).unwrap(), 0);
+
+    // Open files can't be removed or moved
+    assert_eq!(vfs.remove("/log.txt", false).err(), Some(FsError::Busy));
+    assert_eq!(vfs.rename("/log.txt", "/other.txt").err(), Some(FsError::Busy));
+    assert_eq!(vfs.open_files().len(), 2);
+
+    vfs.close(a).unwrap();
+    assert_eq!(vfs.remove("/log.txt", false).err(), Some(FsError::Busy));
+    vfs.close(b).unwrap();
+    vfs.remove("/log.txt", false).unwrap();
+
+    assert_eq!(vfs.open("/", OpenFlags::READ).err(), Some(FsError::IsADirectory));
+}
+
+#[test_case]
+fn append_and_truncate() {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Box::new(fat_volume())).unwrap();
+
+    let fd = vfs.open("/a.txt", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    vfs.write(fd, b"0123456789").unwrap();
+    vfs.close(fd).unwrap();
+
+    let fd = vfs.open("/a.txt", OpenFlags::WRITE | OpenFlags::APPEND).unwrap();
+    vfs.seek(fd, SeekFrom::Start(0)).unwrap();
+    vfs.write(fd, b"ab").unwrap();
+    assert_eq!(vfs.seek(fd, SeekFrom::Current(0)).unwrap(), 12);
+    vfs.close(fd).unwrap();
+
+    let fd = vfs.open("/a.txt", OpenFlags::READ | OpenFlags::WRITE | OpenFlags::TRUNCATE).unwrap();
+    assert_eq!(vfs.seek(fd, SeekFrom::End(0)).unwrap(), 0);
+
+    // Seeking past the end and writing leaves zeros behind
+    vfs.seek(fd, SeekFrom::Start(3)).unwrap();
+    vfs.write(fd, b"x").unwrap();
+    vfs.seek(fd, SeekFrom::Start(0)).unwrap();
+    let mut buf = [0xFFu8; 8];
+    assert_eq!(vfs.read(fd, &mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"\0\0\0x");
+    vfs.close(fd).unwrap();
+}
+
+#[test_case]
+fn open_files_pin_their_mount() {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Box::new(fat_volume())).unwrap();
+    vfs.create("/mnt", NodeKind::Directory).unwrap();
+    vfs.mount("/mnt", Box::new(fat_volume())).unwrap();
+
+    let fd = vfs.open("/mnt/f", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    assert_eq!(vfs.unmount("/mnt").err(), Some(FsError::Busy));
+    vfs.close(fd).unwrap();
+    vfs.unmount("/mnt").unwrap();
+
+    // Closed descriptors are reused, and there is a limit
+    let fds: Vec<_> = (0..MAX_OPEN_FILES)
+        .map(|_| vfs.open("/mnt", OpenFlags::READ).err())
+        .collect();
+    assert!(fds.iter().all(|err| *err == Some(FsError::IsADirectory)));
+
+    vfs.create("/f", NodeKind::File).unwrap();
+    let fds: Vec<_> = (0..MAX_OPEN_FILES).map(|_| vfs.open("/f", OpenFlags::READ).unwrap()).collect();
+    assert_eq!(vfs.open("/f", OpenFlags::READ).err(), Some(FsError::TooManyOpenFiles));
+    vfs.close(fds[3]).unwrap();
+    assert_eq!(vfs.open("/f", OpenFlags::READ).unwrap(), fds[3]);
+}